@@ -1,10 +1,10 @@
 use std::marker::PhantomData;
-use cgmath::{Matrix, Matrix4, Point3, Vector3, vec3};
-use cgmath::{Transform, AffineMatrix3};
+use cgmath::{Matrix, Matrix4, PerspectiveFov, Point3, Rad, Vector3, vec3};
 use gfx;
 use gfx::attrib::Floater;
 use gfx::traits::*;
 use gfx_phase;
+use gfx_scene;
 
 
 gfx_vertex!( Vertex {
@@ -118,13 +118,11 @@ struct Entity<R: gfx::Resources> {
 pub struct App<R: gfx::Resources> {
     phase: gfx_phase::CachedPhase<R, Material, ViewInfo, Technique<R>>,
     entities: Vec<Entity<R>>,
-    proj_view: Matrix4<f32>,
+    camera: gfx_scene::camera::Perspective<f32>,
 }
 
 impl<R: gfx::Resources> App<R> {
     pub fn new<F: gfx::Factory<R>>(factory: &mut F, aspect: f32) -> App<R> {
-        use cgmath::{perspective, deg};
-
         let vertex_data = [
             Vertex::new(-1, -1, -1),
             Vertex::new(0, 2, -1),
@@ -151,8 +149,13 @@ impl<R: gfx::Resources> App<R> {
                                      .with_sort(gfx_phase::sort::back_to_front)
                                      .with_cache();
 
-        let proj = perspective(deg(90.0f32), aspect, 1.0, 10.0);
-        let view: AffineMatrix3<f32> = Transform::look_at(
+        let mut camera = gfx_scene::camera::Static::new(PerspectiveFov {
+            fovy: Rad { s: ::std::f32::consts::FRAC_PI_2 },
+            aspect: aspect,
+            near: 1.0,
+            far: 10.0,
+        });
+        camera.look_at(
             &Point3::new(1.5f32, -5.0, 3.0),
             &Point3::new(0f32, 0.0, 0.0),
             &Vector3::unit_z(),
@@ -161,12 +164,13 @@ impl<R: gfx::Resources> App<R> {
         App {
             phase: phase,
             entities: entities.collect(),
-            proj_view: proj.mul_m(&view.mat),
+            camera: camera,
         }
     }
 
     pub fn render<S: gfx::Stream<R>>(&mut self, stream: &mut S) {
         use gfx_phase::AbstractPhase;
+        use gfx_scene::Camera;
         let clear_data = gfx::ClearData {
             color: [0.3, 0.3, 0.3, 1.0],
             depth: 1.0,
@@ -174,13 +178,14 @@ impl<R: gfx::Resources> App<R> {
         };
         stream.clear(clear_data);
 
+        let proj_view = self.camera.get_view_projection();
         for ent in self.entities.iter() {
             use std::f32::consts::PI;
             let angle = ent.material.alpha * PI * 2.0;
             let model = Matrix4::from_translation(&vec3(
                 3.0 * angle.cos(), 0.0, 3.0 * angle.sin()
             ));
-            let view_info = ViewInfo(self.proj_view.mul_m(&model));
+            let view_info = ViewInfo(proj_view.mul_m(&model));
             self.phase.enqueue(&ent.mesh, &ent.slice, &ent.material, &view_info).unwrap();
         }
         
@@ -4,6 +4,7 @@ extern crate glutin;
 extern crate gfx;
 extern crate gfx_window_glutin;
 extern crate gfx_phase;
+extern crate gfx_scene;
 
 mod app;
 
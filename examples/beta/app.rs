@@ -112,22 +112,6 @@ impl gfx_scene::ViewInfo<f32, Transform<f32>> for ViewInfo {
     }
 }
 
-struct Camera<S>(cgmath::Ortho<S>);
-
-impl<S: cgmath::BaseFloat> gfx_scene::Node for Camera<S> {
-    type Transform = Transform<S>;
-    fn get_transform(&self) -> Transform<S> {
-        cgmath::Transform::one()
-    }
-}
-
-impl<S: cgmath::BaseFloat> gfx_scene::Camera<S> for Camera<S> {
-    type Projection = cgmath::Ortho<S>;
-    fn get_projection(&self) -> cgmath::Ortho<S> {
-        self.0.clone()
-    }
-}
-
 struct Entity<S, R: gfx::Resources> {
     mesh: gfx::Mesh<R>,
     fragments: Vec<gfx_scene::Fragment<R, Material>>,
@@ -160,7 +144,9 @@ impl<S: Clone, R: gfx::Resources> gfx_scene::Entity<R, Material> for Entity<S, R
 pub struct App<R: gfx::Resources> {
     phase: gfx_phase::Phase<R, Material, ViewInfo, Technique<R>, ()>,
     scene: Vec<Entity<f32, R>>,
-    camera: Camera<f32>,
+    camera: gfx_scene::camera::Orthographic<f32>,
+    culler: gfx_scene::Temporal<f32, collision::Aabb3<f32>,
+        gfx_scene::Frustum<f32, collision::Aabb3<f32>>>,
 }
 
 impl<R: gfx::Resources> App<R> {
@@ -174,10 +160,13 @@ impl<R: gfx::Resources> App<R> {
 
         let mesh = factory.create_mesh(&vertex_data);
         let slice = mesh.to_slice(gfx::PrimitiveType::TriangleStrip);
+        let bound = gfx_scene::bound::aabb_from_points(vertex_data.iter().map(|v|
+            cgmath::Point3::new(v.pos[0].0 as f32, v.pos[1].0 as f32, 0.0)
+        )).unwrap();
 
         let num = 10usize;
         let entities = (0..num).map(|i| {
-            use cgmath::{Point3, vec2};
+            use cgmath::vec2;
             let angle = (i as f32) / (num as f32) * std::f32::consts::PI * 2.0;
             let offset = vec2(4.0 * angle.cos(), 4.0 * angle.sin());
             Entity {
@@ -187,7 +176,7 @@ impl<R: gfx::Resources> App<R> {
                     rot: cgmath::Quaternion::one(),
                     disp: cgmath::vec3(offset.x, offset.y, 0.0),
                 },
-                bound: collision::Aabb3::new(Point3::new(0f32, 0.0, 0.0), Point3::new(1.0, 1.0, 0.0)),
+                bound: bound.clone(),
                 fragments: vec![
                     gfx_scene::Fragment::new(Material, slice.clone()),
                 ],
@@ -197,7 +186,7 @@ impl<R: gfx::Resources> App<R> {
         let phase = gfx_phase::Phase::new("Main", Technique::new(factory))
                                      .with_sort(gfx_phase::sort::program);
 
-        let camera = Camera(cgmath::Ortho {
+        let camera = gfx_scene::camera::Static::new(cgmath::Ortho {
             left: -SCALE, right: SCALE,
             bottom: -SCALE, top: SCALE,
             near: -1f32, far: 1f32,
@@ -207,6 +196,7 @@ impl<R: gfx::Resources> App<R> {
             phase: phase,
             scene: entities,
             camera: camera,
+            culler: gfx_scene::Temporal::new(gfx_scene::Frustum::new()),
         }
     }
 
@@ -218,8 +208,7 @@ impl<R: gfx::Resources> App<R> {
             stencil: 0,
         };
         stream.clear(clear_data);
-        let mut culler = gfx_scene::Frustum::new();
-        gfx_scene::Context::new(&mut culler, &self.camera)
+        gfx_scene::Context::new(&mut self.culler, &self.camera)
                 .draw(self.scene.iter(), &mut self.phase, stream)
                 .unwrap();
     }
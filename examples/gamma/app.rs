@@ -0,0 +1,290 @@
+use std::marker::PhantomData;
+use cgmath;
+use cgmath::{Matrix4, Point3, Rad, Vector3};
+use collision;
+use gfx;
+use gfx::traits::*;
+use gfx_phase;
+use gfx_scene;
+use gfx_scene::Node;
+use gfx_scene::instance::{Group, InstanceInfo};
+use gfx_scene::sprite::{self, Facing};
+
+gfx_vertex!( Vertex {
+    a_Pos@ pos: [f32; 2],
+    a_TexCoord@ tex_coord: [f32; 2],
+});
+
+impl Vertex {
+    fn new(x: f32, y: f32) -> Vertex {
+        Vertex {
+            pos: [x, y],
+            tex_coord: [x + 0.5, y + 0.5],
+        }
+    }
+}
+
+gfx_parameters!( Params {
+    u_Transform@ transform: [[f32; 4]; 4],
+    u_Model@ model: [[f32; 4]; 4],
+    u_Color@ color: [f32; 4],
+    u_Tint@ tint: f32,
+});
+
+// The instance matrices are relative to the group model transform.
+static VERTEX_SRC: &'static [u8] = b"
+    #version 150 core
+    in vec2 a_Pos;
+    in vec2 a_TexCoord;
+    in vec4 a_Transform0;
+    in vec4 a_Transform1;
+    in vec4 a_Transform2;
+    in vec4 a_Transform3;
+    uniform mat4 u_Transform;
+    uniform mat4 u_Model;
+    uniform vec4 u_Color;
+    uniform float u_Tint;
+    out vec2 v_TexCoord;
+    out vec4 v_Color;
+    void main() {
+        mat4 instance = mat4(a_Transform0, a_Transform1, a_Transform2, a_Transform3);
+        vec3 center = (u_Model * a_Transform3).xyz;
+        vec3 tint = vec3(0.5 + 0.1 * center.x, 1.0, 0.5 + 0.1 * center.z);
+        v_TexCoord = a_TexCoord;
+        v_Color = vec4(u_Color.rgb * mix(vec3(1.0), tint, u_Tint), u_Color.a);
+        gl_Position = u_Transform * instance * vec4(a_Pos, 0.0, 1.0);
+    }
+";
+
+static FRAGMENT_SRC: &'static [u8] = b"
+    #version 150 core
+    in vec2 v_TexCoord;
+    in vec4 v_Color;
+    out vec4 o_Color;
+    void main() {
+        float r = length(v_TexCoord - vec2(0.5)) * 2.0;
+        o_Color = vec4(v_Color.rgb, v_Color.a * clamp(1.0 - r, 0.0, 1.0));
+    }
+";
+
+// Defining the technique, material, and view info
+
+// The sprites are drawn as instanced groups, a call per group, so the
+// phase only sorts the groups by the depth of their reference instance.
+struct Technique<R: gfx::Resources> {
+    program: gfx::handle::Program<R>,
+    state: gfx::DrawState,
+}
+
+impl<R: gfx::Resources> Technique<R> {
+    pub fn new<F: Factory<R>>(factory: &mut F) -> Technique<R> {
+        let program = factory.link_program(VERTEX_SRC, FRAGMENT_SRC).unwrap();
+        Technique {
+            program: program,
+            state: gfx::DrawState::new()
+                                  .depth(gfx::state::Comparison::LessEqual, false)
+                                  .blend(gfx::BlendPreset::Alpha),
+        }
+    }
+}
+
+struct Material {
+    color: [f32; 4],
+    // how much the color is tinted by the sprite position
+    tint: f32,
+}
+
+impl gfx_phase::Material for Material {}
+
+#[derive(Clone, Copy)]
+struct ViewInfo {
+    mvp: Matrix4<f32>,
+    model: Matrix4<f32>,
+    instances: gfx::InstanceCount,
+}
+
+impl gfx_phase::ToDepth for ViewInfo {
+    type Depth = f32;
+    fn to_depth(&self) -> f32 {
+        self.mvp[3][2] / self.mvp[3][3]
+    }
+}
+
+impl<R: gfx::Resources> gfx_phase::Technique<R, Material, ViewInfo>
+for Technique<R> {
+    type Kernel = ();
+    type Params = Params<R>;
+
+    fn test(&self, _: &gfx::Mesh<R>, _: &Material) -> Option<()> {
+        Some(())
+    }
+
+    fn compile<'a>(&'a self, _: ()) -> gfx_phase::TechResult<'a, R, Params<R>> {
+        (   &self.program,
+            Params {
+                transform: [[0.0; 4]; 4],
+                model: [[0.0; 4]; 4],
+                color: [1.0; 4],
+                tint: 0.0,
+                _r: PhantomData,
+            },
+            &self.state,
+            None,
+        )
+    }
+
+    fn fix_params(&self, mat: &Material, space: &ViewInfo, params: &mut Params<R>) {
+        params.transform = space.mvp.into();
+        params.model = space.model.into();
+        params.color = mat.color;
+        params.tint = mat.tint;
+    }
+
+    fn fix_instances(&self, space: &ViewInfo, instances: &mut Option<gfx::InstanceCount>) {
+        *instances = Some(space.instances);
+    }
+}
+
+//----------------------------------------
+
+type Transform = gfx_scene::camera::Transform<f32>;
+
+impl gfx_scene::ViewInfo<f32, Transform> for ViewInfo {
+    fn new(mvp: Matrix4<f32>, _: Transform, model: Transform) -> ViewInfo {
+        ViewInfo {
+            mvp: mvp,
+            model: model.into(),
+            instances: 1,
+        }
+    }
+}
+
+impl InstanceInfo for ViewInfo {
+    fn set_instances(&mut self, num: gfx::InstanceCount) {
+        self.instances = num;
+    }
+}
+
+//----------------------------------------
+
+/// Sprites sharing the quad, the material, and the facing mode.
+struct Sprites<R: gfx::Resources> {
+    group: Group<R, Material, Transform, collision::Sphere<f32>>,
+    positions: Vec<Point3<f32>>,
+    size: f32,
+    facing: Facing<f32>,
+}
+
+impl<R: gfx::Resources> Sprites<R> {
+    fn new<F: gfx::Factory<R>>(factory: &mut F, mesh: &gfx::Mesh<R>, slice: &gfx::Slice<R>,
+           material: Material, positions: Vec<Point3<f32>>, size: f32, facing: Facing<f32>,
+           camera: &Transform) -> Sprites<R> {
+        let transforms = positions.iter()
+            .map(|p| sprite::face(p, size, facing, camera))
+            .collect();
+        let fragments = vec![gfx_scene::Fragment::new(material, slice.clone())];
+        Sprites {
+            group: Group::new(factory, mesh.clone(), fragments, transforms,
+                              sprite::get_quad_bound()),
+            positions: positions,
+            size: size,
+            facing: facing,
+        }
+    }
+
+    fn orient(&mut self, camera: &Transform) {
+        use cgmath::{EuclideanVector, Point};
+        // back to front within the group, for the alpha blending
+        let eye = Point3::from_vec(&camera.disp);
+        self.positions.sort_by(|a, b| {
+            let (da, db) = (a.sub_p(&eye).length2(), b.sub_p(&eye).length2());
+            db.partial_cmp(&da).unwrap()
+        });
+        for (t, p) in self.group.transforms.iter_mut().zip(self.positions.iter()) {
+            *t = sprite::face(p, self.size, self.facing, camera);
+        }
+    }
+}
+
+//----------------------------------------
+
+pub struct App<R: gfx::Resources> {
+    phase: gfx_phase::CachedPhase<R, Material, ViewInfo, Technique<R>>,
+    particles: Sprites<R>,
+    posts: Sprites<R>,
+    camera: gfx_scene::camera::Orbit<f32>,
+    culler: gfx_scene::Frustum<f32, collision::Sphere<f32>>,
+}
+
+impl<R: gfx::Resources> App<R> {
+    pub fn new<F: gfx::Factory<R>>(factory: &mut F, aspect: f32) -> App<R> {
+        let vertex_data = [
+            Vertex::new(-0.5, 0.5),
+            Vertex::new(-0.5, -0.5),
+            Vertex::new(0.5, 0.5),
+            Vertex::new(0.5, -0.5),
+        ];
+        let mesh = factory.create_mesh(&vertex_data);
+        let slice = mesh.to_slice(gfx::PrimitiveType::TriangleStrip);
+
+        let mut camera = gfx_scene::camera::Orbit::new(Point3::new(0.0, 2.0, 0.0), 16.0,
+            cgmath::PerspectiveFov {
+                fovy: Rad { s: 1.0f32 },
+                aspect: aspect,
+                near: 0.1,
+                far: 100.0,
+            });
+        camera.rotate(Rad { s: 0.0 }, Rad { s: 0.4 });
+        let cam_transform = camera.get_transform();
+
+        let num = 10;
+        // a floating cloud of particles
+        let mut positions = Vec::new();
+        for i in 0..num {
+            for j in 0..num {
+                let (x, z) = (i as f32 - 4.5, j as f32 - 4.5);
+                positions.push(Point3::new(x, 3.0 + 0.5 * (x * z * 0.1).sin(), z));
+            }
+        }
+        let particles = Sprites::new(factory, &mesh, &slice,
+            Material { color: [1.0, 0.5, 1.0, 0.8], tint: 1.0 },
+            positions, 0.8, Facing::Spherical, &cam_transform);
+        // a ring of upright posts on the ground
+        let positions = (0..num).map(|i| {
+            let angle = (i as f32) / (num as f32) * ::std::f32::consts::PI * 2.0;
+            Point3::new(8.0 * angle.cos(), 1.0, 8.0 * angle.sin())
+        }).collect();
+        let posts = Sprites::new(factory, &mesh, &slice,
+            Material { color: [0.2, 0.8, 0.3, 1.0], tint: 0.0 },
+            positions, 2.0, Facing::Cylindrical(Vector3::unit_y()), &cam_transform);
+
+        let phase = gfx_phase::Phase::new("Sprites", Technique::new(factory))
+                                     .with_sort(gfx_phase::sort::back_to_front)
+                                     .with_cache();
+
+        App {
+            phase: phase,
+            particles: particles,
+            posts: posts,
+            camera: camera,
+            culler: gfx_scene::Frustum::new(),
+        }
+    }
+
+    pub fn render<S: gfx::Stream<R>>(&mut self, stream: &mut S) {
+        let clear_data = gfx::ClearData {
+            color: [0.3, 0.3, 0.3, 1.0],
+            depth: 1.0,
+            stencil: 0,
+        };
+        stream.clear(clear_data);
+        self.camera.rotate(Rad { s: 0.005 }, Rad { s: 0.0 });
+        let cam_transform = self.camera.get_transform();
+        self.particles.orient(&cam_transform);
+        self.posts.orient(&cam_transform);
+        let groups = vec![&self.particles.group, &self.posts.group];
+        gfx_scene::Context::new(&mut self.culler, &self.camera)
+                .draw_instanced(groups.into_iter(), &mut self.phase, stream)
+                .unwrap();
+    }
+}
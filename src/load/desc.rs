@@ -0,0 +1,404 @@
+//! Scene description, serializable to RON and JSON.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use cgmath;
+use cgmath::{Decomposed, Ortho, PerspectiveFov, Quaternion, Rad, Vector3};
+use collision::Aabb3;
+use gfx;
+use gfx_scene;
+use gfx_scene::camera::DepthRange;
+use ron;
+use serde_json;
+use {Camera, Entity, Error, Material, Projection, Scene, Transform};
+
+/// Serialization format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Rusty Object Notation.
+    Ron,
+    /// JSON.
+    Json,
+}
+
+impl Format {
+    /// Guess the format by the file extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("ron") => Some(Format::Ron),
+            Some("json") => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Node transform description.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransformDesc {
+    /// Translation.
+    pub translation: [f32; 3],
+    /// Rotation quaternion, as `[x, y, z, w]`.
+    pub rotation: [f32; 4],
+    /// Uniform scale.
+    pub scale: f32,
+}
+
+impl From<Transform> for TransformDesc {
+    fn from(t: Transform) -> TransformDesc {
+        TransformDesc {
+            translation: [t.disp.x, t.disp.y, t.disp.z],
+            rotation: [t.rot.v.x, t.rot.v.y, t.rot.v.z, t.rot.s],
+            scale: t.scale,
+        }
+    }
+}
+
+impl From<TransformDesc> for Transform {
+    fn from(t: TransformDesc) -> Transform {
+        let (p, r) = (t.translation, t.rotation);
+        Decomposed {
+            scale: t.scale,
+            rot: Quaternion::new(r[3], r[0], r[1], r[2]),
+            disp: Vector3::new(p[0], p[1], p[2]),
+        }
+    }
+}
+
+fn default_true() -> bool { true }
+
+fn default_layers() -> gfx_scene::Layers { gfx_scene::LAYER_DEFAULT }
+
+/// Drawable node description.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NodeDesc {
+    /// Node name.
+    pub name: String,
+    /// Index of the parent node, which has to precede this one.
+    #[serde(default)]
+    pub parent: Option<usize>,
+    /// Local transform, relative to the parent.
+    pub transform: TransformDesc,
+    /// Mesh reference, resolved by the `Resolver`. Nodes without meshes
+    /// only serve as parents.
+    #[serde(default)]
+    pub mesh: Option<String>,
+    /// Materials of the mesh fragments.
+    #[serde(default)]
+    pub materials: Vec<Material>,
+    /// Visibility flag.
+    #[serde(default = "default_true")]
+    pub visible: bool,
+    /// Render layers.
+    #[serde(default = "default_layers")]
+    pub layers: gfx_scene::Layers,
+    /// Whether the node casts shadows.
+    #[serde(default = "default_true")]
+    pub shadow_caster: bool,
+    /// Whether the node receives shadows.
+    #[serde(default = "default_true")]
+    pub shadow_receiver: bool,
+}
+
+impl NodeDesc {
+    /// Describe a loaded entity, with the world transform as the local
+    /// one, and the mesh referenced by a given name.
+    pub fn from_entity<R: gfx::Resources>(entity: &Entity<R>, mesh: Option<String>)
+                       -> NodeDesc {
+        NodeDesc {
+            name: entity.name.clone(),
+            parent: None,
+            transform: entity.transform.clone().into(),
+            mesh: mesh,
+            materials: entity.fragments.iter().map(|f| f.material.clone()).collect(),
+            visible: entity.visible,
+            layers: entity.layers,
+            shadow_caster: entity.shadow_caster,
+            shadow_receiver: entity.shadow_receiver,
+        }
+    }
+}
+
+/// Clip space depth convention description.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DepthRangeDesc {
+    /// Depth goes from -1 at near to 1 at far.
+    NegativeOneToOne,
+    /// Depth goes from 0 at near to 1 at far.
+    ZeroToOne,
+    /// Depth goes from 1 at near to 0 at far.
+    Reversed,
+}
+
+impl Default for DepthRangeDesc {
+    fn default() -> DepthRangeDesc {
+        DepthRangeDesc::NegativeOneToOne
+    }
+}
+
+impl From<DepthRange> for DepthRangeDesc {
+    fn from(d: DepthRange) -> DepthRangeDesc {
+        match d {
+            DepthRange::NegativeOneToOne => DepthRangeDesc::NegativeOneToOne,
+            DepthRange::ZeroToOne => DepthRangeDesc::ZeroToOne,
+            DepthRange::Reversed => DepthRangeDesc::Reversed,
+        }
+    }
+}
+
+impl From<DepthRangeDesc> for DepthRange {
+    fn from(d: DepthRangeDesc) -> DepthRange {
+        match d {
+            DepthRangeDesc::NegativeOneToOne => DepthRange::NegativeOneToOne,
+            DepthRangeDesc::ZeroToOne => DepthRange::ZeroToOne,
+            DepthRangeDesc::Reversed => DepthRange::Reversed,
+        }
+    }
+}
+
+/// Camera projection description.
+#[allow(missing_docs)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ProjectionDesc {
+    /// Perspective projection, with the vertical field of view in radians.
+    Perspective { fovy: f32, aspect: f32, near: f32, far: f32 },
+    /// Perspective projection with the far plane at infinity.
+    InfinitePerspective {
+        fovy: f32, aspect: f32, near: f32,
+        #[serde(default)]
+        depth: DepthRangeDesc,
+    },
+    /// Orthographic projection.
+    Orthographic { left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32 },
+}
+
+impl From<ProjectionDesc> for Projection {
+    fn from(p: ProjectionDesc) -> Projection {
+        match p {
+            ProjectionDesc::Perspective { fovy, aspect, near, far } =>
+                Projection::Perspective(PerspectiveFov {
+                    fovy: Rad { s: fovy },
+                    aspect: aspect,
+                    near: near,
+                    far: far,
+                }),
+            ProjectionDesc::InfinitePerspective { fovy, aspect, near, depth } =>
+                Projection::InfinitePerspective(gfx_scene::camera::InfinitePerspective {
+                    fovy: Rad { s: fovy },
+                    aspect: aspect,
+                    near: near,
+                    depth: depth.into(),
+                }),
+            ProjectionDesc::Orthographic { left, right, bottom, top, near, far } =>
+                Projection::Orthographic(Ortho {
+                    left: left,
+                    right: right,
+                    bottom: bottom,
+                    top: top,
+                    near: near,
+                    far: far,
+                }),
+        }
+    }
+}
+
+impl From<Projection> for ProjectionDesc {
+    fn from(p: Projection) -> ProjectionDesc {
+        match p {
+            Projection::Perspective(p) => ProjectionDesc::Perspective {
+                fovy: p.fovy.s,
+                aspect: p.aspect,
+                near: p.near,
+                far: p.far,
+            },
+            Projection::InfinitePerspective(p) => ProjectionDesc::InfinitePerspective {
+                fovy: p.fovy.s,
+                aspect: p.aspect,
+                near: p.near,
+                depth: p.depth.into(),
+            },
+            Projection::Orthographic(p) => ProjectionDesc::Orthographic {
+                left: p.left,
+                right: p.right,
+                bottom: p.bottom,
+                top: p.top,
+                near: p.near,
+                far: p.far,
+            },
+        }
+    }
+}
+
+/// Camera description.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CameraDesc {
+    /// Camera name.
+    pub name: String,
+    /// Index of the parent node.
+    #[serde(default)]
+    pub parent: Option<usize>,
+    /// Local transform, relative to the parent.
+    pub transform: TransformDesc,
+    /// Projection.
+    pub projection: ProjectionDesc,
+}
+
+impl CameraDesc {
+    /// Describe a loaded camera, with the world transform as the local one.
+    pub fn from_camera(name: String, camera: &Camera) -> CameraDesc {
+        CameraDesc {
+            name: name,
+            parent: None,
+            transform: camera.transform.clone().into(),
+            projection: camera.projection.clone().into(),
+        }
+    }
+}
+
+/// Scene description.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SceneDesc {
+    /// Node hierarchy, parents preceding children.
+    pub nodes: Vec<NodeDesc>,
+    /// Cameras.
+    #[serde(default)]
+    pub cameras: Vec<CameraDesc>,
+}
+
+/// Mesh resolved by the `Resolver`.
+pub struct ResolvedMesh<R: gfx::Resources> {
+    /// Vertex data.
+    pub mesh: gfx::Mesh<R>,
+    /// Slices of the fragments, matched with the node materials.
+    pub slices: Vec<gfx::Slice<R>>,
+    /// Local bound.
+    pub bound: Aabb3<f32>,
+}
+
+/// Provider of the resources referenced by a scene description.
+pub trait Resolver<R: gfx::Resources> {
+    /// Get the mesh by its reference.
+    fn resolve_mesh(&mut self, &str) -> Result<ResolvedMesh<R>, Error>;
+    /// Finalize a material, e.g. by loading its textures.
+    fn resolve_material(&mut self, material: &Material) -> Result<Material, Error> {
+        Ok(material.clone())
+    }
+}
+
+impl SceneDesc {
+    /// Describe a loaded scene, e.g. to save it. The hierarchy is already
+    /// flattened, so the nodes have no parents. The meshes are referenced
+    /// by the names given by `mesh_name` for each entity index, and the
+    /// cameras are named by their indices.
+    pub fn from_scene<R, F>(scene: &Scene<R>, mut mesh_name: F) -> SceneDesc where
+        R: gfx::Resources,
+        F: FnMut(usize, &Entity<R>) -> Option<String>,
+    {
+        SceneDesc {
+            nodes: scene.entities.iter().enumerate().map(|(i, ent)| {
+                NodeDesc::from_entity(ent, mesh_name(i, ent))
+            }).collect(),
+            cameras: scene.cameras.iter().enumerate().map(|(i, cam)| {
+                CameraDesc::from_camera(format!("camera{}", i), cam)
+            }).collect(),
+        }
+    }
+
+    /// Parse a description.
+    pub fn parse(text: &str, format: Format) -> Result<SceneDesc, Error> {
+        match format {
+            Format::Ron => ron::de::from_str(text).map_err(Error::RonParse),
+            Format::Json => serde_json::from_str(text).map_err(Error::Json),
+        }
+    }
+
+    /// Write the description into a string.
+    pub fn write(&self, format: Format) -> Result<String, Error> {
+        match format {
+            Format::Ron => ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+                               .map_err(Error::Ron),
+            Format::Json => serde_json::to_string_pretty(self).map_err(Error::Json),
+        }
+    }
+
+    /// Load a description from a file, with the format given by the extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SceneDesc, Error> {
+        let format = match Format::from_path(&path) {
+            Some(f) => f,
+            None => return Err(Error::UnknownFormat),
+        };
+        let mut text = String::new();
+        try!(try!(File::open(path)).read_to_string(&mut text));
+        SceneDesc::parse(&text, format)
+    }
+
+    /// Save the description into a file, with the format given by the extension.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let format = match Format::from_path(&path) {
+            Some(f) => f,
+            None => return Err(Error::UnknownFormat),
+        };
+        let text = try!(self.write(format));
+        try!(try!(File::create(path)).write_all(text.as_bytes()));
+        Ok(())
+    }
+
+    /// Build the scene, resolving the meshes and materials. Fragments
+    /// without a matching material get the default one.
+    pub fn instantiate<R, X>(&self, resolver: &mut X) -> Result<Scene<R>, Error> where
+        R: gfx::Resources,
+        X: Resolver<R>,
+    {
+        use cgmath::Transform as Tr;
+        let mut worlds: Vec<Transform> = Vec::with_capacity(self.nodes.len());
+        let mut scene = Scene {
+            entities: Vec::new(),
+            cameras: Vec::new(),
+        };
+        for (i, node) in self.nodes.iter().enumerate() {
+            let local: Transform = node.transform.clone().into();
+            let world = match node.parent {
+                Some(p) if p < i => worlds[p].concat(&local),
+                Some(p) => return Err(Error::InvalidParent(p)),
+                None => local,
+            };
+            worlds.push(world.clone());
+            let mesh = match node.mesh {
+                Some(ref name) => try!(resolver.resolve_mesh(name)),
+                None => continue,
+            };
+            let mut fragments = Vec::with_capacity(mesh.slices.len());
+            for (j, slice) in mesh.slices.into_iter().enumerate() {
+                let material = match node.materials.get(j) {
+                    Some(m) => try!(resolver.resolve_material(m)),
+                    None => Material::default(),
+                };
+                fragments.push(gfx_scene::Fragment::new(material, slice));
+            }
+            scene.entities.push(Entity {
+                name: node.name.clone(),
+                mesh: mesh.mesh,
+                fragments: fragments,
+                transform: world,
+                bound: mesh.bound,
+                visible: node.visible,
+                layers: node.layers,
+                shadow_caster: node.shadow_caster,
+                shadow_receiver: node.shadow_receiver,
+            });
+        }
+        for cam in self.cameras.iter() {
+            let local: Transform = cam.transform.clone().into();
+            let world = match cam.parent {
+                Some(p) if p < worlds.len() => worlds[p].concat(&local),
+                Some(p) => return Err(Error::InvalidParent(p)),
+                None => local,
+            };
+            scene.cameras.push(Camera {
+                transform: world,
+                projection: cam.projection.clone().into(),
+            });
+        }
+        Ok(scene)
+    }
+}
@@ -0,0 +1,457 @@
+//! glTF 2.0 loader.
+
+use std::collections::HashMap;
+use std::path::Path;
+use cgmath::{Decomposed, Matrix4, Ortho, PerspectiveFov, Point3, Quaternion, Rad, Vector3};
+use collision::Aabb3;
+use gfx;
+use gfx::traits::*;
+use gfx_scene;
+use gltf_lib;
+use gltf_lib::mesh::Mode;
+use {AlphaMode, Camera, Entity, Error, Material, Projection, Scene, Transform, Vertex};
+
+impl From<gltf_lib::Error> for Error {
+    fn from(e: gltf_lib::Error) -> Error {
+        Error::Gltf(e)
+    }
+}
+
+/// Mesh data shared by all the nodes referencing it.
+#[derive(Clone)]
+struct Mesh<R: gfx::Resources> {
+    mesh: gfx::Mesh<R>,
+    fragments: Vec<gfx_scene::Fragment<R, Material>>,
+    bound: Aabb3<f32>,
+}
+
+fn convert_material(mat: &gltf_lib::Material) -> Material {
+    let pbr = mat.pbr_metallic_roughness();
+    let texture = pbr.base_color_texture().and_then(|info| {
+        match info.texture().source().source() {
+            gltf_lib::image::Source::Uri { uri, .. } => Some(uri.to_string()),
+            gltf_lib::image::Source::View { .. } => None,
+        }
+    });
+    Material {
+        name: mat.name().unwrap_or("").to_string(),
+        base_color: pbr.base_color_factor(),
+        base_color_texture: texture,
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        emissive: mat.emissive_factor(),
+        alpha: match mat.alpha_mode() {
+            gltf_lib::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf_lib::material::AlphaMode::Mask => AlphaMode::Mask(mat.alpha_cutoff().unwrap_or(0.5)),
+            gltf_lib::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        double_sided: mat.double_sided(),
+    }
+}
+
+/// Linear map, given by its columns.
+type Linear = [[f32; 3]; 3];
+
+const IDENTITY: Linear = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+const IDENTITY4: [[f32; 4]; 4] = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0],
+                                  [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn apply(l: &Linear, v: [f32; 3]) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for i in 0..3 {
+        out[i] = l[0][i] * v[0] + l[1][i] * v[1] + l[2][i] * v[2];
+    }
+    out
+}
+
+fn determinant(l: &Linear) -> f32 {
+    dot(l[0], cross(l[1], l[2]))
+}
+
+/// Convert a primitive mode, along with its indices. Line loops become
+/// closed line strips, and with a mirroring transform the triangle strips
+/// and fans become lists with the winding swapped.
+fn convert_mode(mode: Mode, indices: &mut Vec<u32>, mirror: bool) -> gfx::PrimitiveType {
+    let triangles: Vec<[u32; 3]> = match mode {
+        Mode::Points => return gfx::PrimitiveType::Point,
+        Mode::Lines => return gfx::PrimitiveType::Line,
+        Mode::LineStrip => return gfx::PrimitiveType::LineStrip,
+        Mode::LineLoop => {
+            if let Some(&first) = indices.first() {
+                indices.push(first);
+            }
+            return gfx::PrimitiveType::LineStrip
+        },
+        Mode::TriangleStrip if !mirror => return gfx::PrimitiveType::TriangleStrip,
+        Mode::TriangleFan if !mirror => return gfx::PrimitiveType::TriangleFan,
+        Mode::Triangles => indices.chunks(3).filter(|t| t.len() == 3)
+                                  .map(|t| [t[0], t[1], t[2]]).collect(),
+        Mode::TriangleStrip => (2..indices.len()).map(|k| if k % 2 == 0 {
+            [indices[k - 2], indices[k - 1], indices[k]]
+        } else {
+            [indices[k - 1], indices[k - 2], indices[k]]
+        }).collect(),
+        Mode::TriangleFan => (2..indices.len()).map(|k|
+            [indices[0], indices[k - 1], indices[k]]
+        ).collect(),
+    };
+    indices.clear();
+    for t in triangles.iter() {
+        if mirror {
+            indices.extend_from_slice(&[t[0], t[2], t[1]]);
+        } else {
+            indices.extend_from_slice(t);
+        }
+    }
+    gfx::PrimitiveType::TriangleList
+}
+
+/// Load a mesh, with a linear map baked into the vertices.
+fn load_mesh<R, F>(factory: &mut F, mesh: &gltf_lib::Mesh, buffers: &[gltf_lib::buffer::Data],
+                   linear: &Linear) -> Result<Mesh<R>, Error> where
+    R: gfx::Resources,
+    F: gfx::Factory<R>,
+{
+    let mut vertices = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    let mut ranges = Vec::new();
+    let mut bound: Option<Aabb3<f32>> = None;
+    let mirror = determinant(linear) < 0.0;
+    // normals go through the cofactor matrix to stay perpendicular,
+    // keeping their side for mirroring maps
+    let sign = if mirror {-1.0} else {1.0};
+    let cofactor = [cross(linear[1], linear[2]),
+                    cross(linear[2], linear[0]),
+                    cross(linear[0], linear[1])];
+    for prim in mesh.primitives() {
+        let reader = prim.reader(|b| Some(&buffers[b.index()]));
+        let positions: Vec<[f32; 3]> = match reader.read_positions() {
+            Some(iter) => iter.collect(),
+            None => return Err(Error::MissingAttribute("POSITION")),
+        };
+        let normals: Vec<[f32; 3]> = reader.read_normals()
+            .map(|iter| iter.collect())
+            .unwrap_or(vec![[0.0, 0.0, 1.0]; positions.len()]);
+        let tex_coords: Vec<[f32; 2]> = reader.read_tex_coords(0)
+            .map(|tc| tc.into_f32().collect())
+            .unwrap_or(vec![[0.0, 0.0]; positions.len()]);
+        let base = vertices.len() as u32;
+        let mut prim_indices: Vec<u32> = match reader.read_indices() {
+            Some(ind) => ind.into_u32().map(|i| base + i).collect(),
+            None => (0..positions.len() as u32).map(|i| base + i).collect(),
+        };
+        let prim_type = convert_mode(prim.mode(), &mut prim_indices, mirror);
+        let start = indices.len() as u32;
+        indices.extend(prim_indices.into_iter());
+        ranges.push((start, indices.len() as u32, prim_type, convert_material(&prim.material())));
+        for ((p, n), t) in positions.into_iter().zip(normals.into_iter()).zip(tex_coords.into_iter()) {
+            let n = apply(&cofactor, n);
+            let len = dot(n, n).sqrt().max(1e-20) * sign;
+            vertices.push(Vertex {
+                pos: apply(linear, p),
+                normal: [n[0] / len, n[1] / len, n[2] / len],
+                tex_coord: t,
+            });
+        }
+        // the box enclosing the mapped corners of the primitive box
+        let bb = prim.bounding_box();
+        let mut min = Point3::new(::std::f32::INFINITY, ::std::f32::INFINITY, ::std::f32::INFINITY);
+        let mut max = Point3::new(::std::f32::NEG_INFINITY, ::std::f32::NEG_INFINITY,
+                                  ::std::f32::NEG_INFINITY);
+        for i in 0..8 {
+            let c = apply(linear, [
+                if i & 1 == 0 {bb.min[0]} else {bb.max[0]},
+                if i & 2 == 0 {bb.min[1]} else {bb.max[1]},
+                if i & 4 == 0 {bb.min[2]} else {bb.max[2]}]);
+            min = Point3::new(min.x.min(c[0]), min.y.min(c[1]), min.z.min(c[2]));
+            max = Point3::new(max.x.max(c[0]), max.y.max(c[1]), max.z.max(c[2]));
+        }
+        bound = Some(match bound {
+            Some(b) => Aabb3::new(
+                Point3::new(b.min.x.min(min.x), b.min.y.min(min.y), b.min.z.min(min.z)),
+                Point3::new(b.max.x.max(max.x), b.max.y.max(max.y), b.max.z.max(max.z))),
+            None => Aabb3::new(min, max),
+        });
+    }
+    let gmesh = factory.create_mesh(&vertices);
+    let ibuf = factory.create_buffer_index::<u32>(&indices);
+    let fragments = ranges.into_iter().map(|(start, end, prim_type, material)| {
+        gfx_scene::Fragment::new(material, gfx::Slice {
+            start: start,
+            end: end,
+            prim_type: prim_type,
+            kind: gfx::SliceKind::Index32(ibuf.clone(), 0),
+        })
+    }).collect();
+    let zero = Point3::new(0.0, 0.0, 0.0);
+    Ok(Mesh {
+        mesh: gmesh,
+        fragments: fragments,
+        bound: bound.unwrap_or(Aabb3::new(zero, zero)),
+    })
+}
+
+fn convert_camera(cam: &gltf_lib::Camera, transform: Transform) -> Camera {
+    use gltf_lib::camera::Projection as P;
+    let projection = match cam.projection() {
+        P::Perspective(p) => match p.zfar() {
+            Some(far) => Projection::Perspective(PerspectiveFov {
+                fovy: Rad { s: p.yfov() },
+                aspect: p.aspect_ratio().unwrap_or(1.0),
+                near: p.znear(),
+                far: far,
+            }),
+            None => Projection::InfinitePerspective(gfx_scene::camera::InfinitePerspective {
+                fovy: Rad { s: p.yfov() },
+                aspect: p.aspect_ratio().unwrap_or(1.0),
+                near: p.znear(),
+                depth: gfx_scene::camera::DepthRange::NegativeOneToOne,
+            }),
+        },
+        P::Orthographic(o) => Projection::Orthographic(Ortho {
+            left: -o.xmag(),
+            right: o.xmag(),
+            bottom: -o.ymag(),
+            top: o.ymag(),
+            near: o.znear(),
+            far: o.zfar(),
+        }),
+    };
+    Camera {
+        transform: transform,
+        projection: projection,
+    }
+}
+
+/// Convert a column major glTF matrix.
+fn convert_matrix(m: [[f32; 4]; 4]) -> Matrix4<f32> {
+    Matrix4::new(m[0][0], m[0][1], m[0][2], m[0][3],
+                 m[1][0], m[1][1], m[1][2], m[1][3],
+                 m[2][0], m[2][1], m[2][2], m[2][3],
+                 m[3][0], m[3][1], m[3][2], m[3][3])
+}
+
+/// Get the quaternion of a rotation matrix.
+fn convert_rotation(r: &Linear) -> Quaternion<f32> {
+    // element at row `i`, column `j`
+    let m = |i: usize, j: usize| r[j][i];
+    let trace = m(0, 0) + m(1, 1) + m(2, 2);
+    if trace > 0.0 {
+        let t = (trace + 1.0).sqrt() * 2.0;
+        Quaternion::new(0.25 * t, (m(2, 1) - m(1, 2)) / t,
+                        (m(0, 2) - m(2, 0)) / t, (m(1, 0) - m(0, 1)) / t)
+    } else if m(0, 0) > m(1, 1) && m(0, 0) > m(2, 2) {
+        let t = (1.0 + m(0, 0) - m(1, 1) - m(2, 2)).sqrt() * 2.0;
+        Quaternion::new((m(2, 1) - m(1, 2)) / t, 0.25 * t,
+                        (m(0, 1) + m(1, 0)) / t, (m(0, 2) + m(2, 0)) / t)
+    } else if m(1, 1) > m(2, 2) {
+        let t = (1.0 + m(1, 1) - m(0, 0) - m(2, 2)).sqrt() * 2.0;
+        Quaternion::new((m(0, 2) - m(2, 0)) / t, (m(0, 1) + m(1, 0)) / t,
+                        0.25 * t, (m(1, 2) + m(2, 1)) / t)
+    } else {
+        let t = (1.0 + m(2, 2) - m(0, 0) - m(1, 1)).sqrt() * 2.0;
+        Quaternion::new((m(1, 0) - m(0, 1)) / t, (m(0, 2) + m(2, 0)) / t,
+                        (m(1, 2) + m(2, 1)) / t, 0.25 * t)
+    }
+}
+
+/// Get the rotation closest to a linear map, by orthonormalizing its
+/// columns, or the identity if it's degenerate.
+fn get_rotation(l: &Linear) -> Linear {
+    let normalize = |v: [f32; 3]| {
+        let len = dot(v, v).sqrt();
+        if len > 1e-20 {Some([v[0] / len, v[1] / len, v[2] / len])} else {None}
+    };
+    let x = normalize(l[0]);
+    let y = x.and_then(|x| {
+        let d = dot(l[1], x);
+        normalize([l[1][0] - d * x[0], l[1][1] - d * x[1], l[1][2] - d * x[2]])
+    });
+    match (x, y) {
+        (Some(x), Some(y)) => [x, y, cross(x, y)],
+        _ => IDENTITY,
+    }
+}
+
+/// Split a world matrix into the transform of an entity and a linear map
+/// to bake into its mesh. The scale of `Decomposed` is uniform and can't
+/// mirror, so any other linear map is baked as a whole, leaving only the
+/// translation in the transform.
+fn split_transform(mx: &Matrix4<f32>) -> (Transform, Linear) {
+    let linear = [[mx.x.x, mx.x.y, mx.x.z],
+                  [mx.y.x, mx.y.y, mx.y.z],
+                  [mx.z.x, mx.z.y, mx.z.z]];
+    let disp = Vector3::new(mx.w.x, mx.w.y, mx.w.z);
+    let lengths = [dot(linear[0], linear[0]).sqrt(),
+                   dot(linear[1], linear[1]).sqrt(),
+                   dot(linear[2], linear[2]).sqrt()];
+    let scale = lengths[0];
+    let eps = 1e-5 * lengths[0].max(lengths[1]).max(lengths[2]);
+    let similar = scale > 0.0 &&
+        (lengths[1] - scale).abs() <= eps && (lengths[2] - scale).abs() <= eps &&
+        dot(linear[0], linear[1]).abs() <= eps * scale &&
+        dot(linear[0], linear[2]).abs() <= eps * scale &&
+        dot(linear[1], linear[2]).abs() <= eps * scale &&
+        determinant(&linear) > 0.0;
+    if similar {
+        let transform = Decomposed {
+            scale: scale,
+            rot: convert_rotation(&get_rotation(&linear)),
+            disp: disp,
+        };
+        (transform, IDENTITY)
+    } else {
+        let transform = Decomposed {
+            scale: 1.0,
+            rot: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            disp: disp,
+        };
+        (transform, linear)
+    }
+}
+
+struct Loader<'a, R: gfx::Resources, F: 'a> {
+    factory: &'a mut F,
+    buffers: Vec<gltf_lib::buffer::Data>,
+    meshes: HashMap<(usize, [u32; 9]), Mesh<R>>,
+    scene: Scene<R>,
+}
+
+impl<'a, R: gfx::Resources, F: gfx::Factory<R>> Loader<'a, R, F> {
+    fn visit(&mut self, node: gltf_lib::Node, parent: &Matrix4<f32>) -> Result<(), Error> {
+        use cgmath::Matrix;
+        let world_mx = parent.mul_m(&convert_matrix(node.transform().matrix()));
+        let (world, linear) = split_transform(&world_mx);
+        if let Some(mesh) = node.mesh() {
+            let mut bits = [0u32; 9];
+            for (i, v) in linear.iter().flat_map(|c| c.iter()).enumerate() {
+                bits[i] = v.to_bits();
+            }
+            let key = (mesh.index(), bits);
+            if !self.meshes.contains_key(&key) {
+                let m = try!(load_mesh(self.factory, &mesh, &self.buffers, &linear));
+                self.meshes.insert(key, m);
+            }
+            let m = &self.meshes[&key];
+            self.scene.entities.push(Entity {
+                name: node.name().unwrap_or("").to_string(),
+                mesh: m.mesh.clone(),
+                fragments: m.fragments.clone(),
+                transform: world.clone(),
+                bound: m.bound.clone(),
+                visible: true,
+                layers: gfx_scene::LAYER_DEFAULT,
+                shadow_caster: true,
+                shadow_receiver: true,
+            });
+        }
+        if let Some(cam) = node.camera() {
+            // cameras can't bake the map, so they only keep its rotation
+            let transform = if linear == IDENTITY {
+                world.clone()
+            } else {
+                warn!("glTF camera node {:?} has a non-uniform or mirroring scale, ignored",
+                      node.name().unwrap_or(""));
+                Decomposed {
+                    scale: 1.0,
+                    rot: convert_rotation(&get_rotation(&linear)),
+                    disp: world.disp,
+                }
+            };
+            self.scene.cameras.push(convert_camera(&cam, transform));
+        }
+        for child in node.children() {
+            try!(self.visit(child, &world_mx));
+        }
+        Ok(())
+    }
+}
+
+/// Load the default scene of a glTF asset from a local file, along with
+/// the external buffers it references. The images are not decoded, only
+/// referenced by the materials. The node hierarchy is flattened into world
+/// transforms. World transforms that aren't similarities, like non-uniform
+/// or mirroring scales, are baked into the meshes, with the winding of the
+/// mirrored triangles swapped.
+pub fn load<R, F, P>(factory: &mut F, path: P) -> Result<Scene<R>, Error> where
+    R: gfx::Resources,
+    F: gfx::Factory<R>,
+    P: AsRef<Path>,
+{
+    let gltf_lib::Gltf { document, blob } = try!(gltf_lib::Gltf::open(&path));
+    let buffers = try!(gltf_lib::import_buffers(&document, path.as_ref().parent(), blob));
+    let mut loader = Loader {
+        factory: factory,
+        buffers: buffers,
+        meshes: HashMap::new(),
+        scene: Scene {
+            entities: Vec::new(),
+            cameras: Vec::new(),
+        },
+    };
+    let root = convert_matrix(IDENTITY4);
+    if let Some(scene) = document.default_scene().or_else(|| document.scenes().next()) {
+        for node in scene.nodes() {
+            try!(loader.visit(node, &root));
+        }
+    }
+    Ok(loader.scene)
+}
+
+#[cfg(test)]
+mod tests {
+    use gfx::PrimitiveType;
+    use gltf_lib::mesh::Mode;
+    use super::{IDENTITY, convert_matrix, convert_mode, split_transform};
+
+    #[test]
+    fn mirrored_strip() {
+        let mut indices = vec![0, 1, 2, 3];
+        assert_eq!(convert_mode(Mode::TriangleStrip, &mut indices, false), PrimitiveType::TriangleStrip);
+        assert_eq!(indices, vec![0, 1, 2, 3]);
+        assert_eq!(convert_mode(Mode::TriangleStrip, &mut indices, true), PrimitiveType::TriangleList);
+        assert_eq!(indices, vec![0, 2, 1, 2, 3, 1]);
+    }
+
+    #[test]
+    fn mirrored_fan() {
+        let mut indices = vec![0, 1, 2, 3];
+        assert_eq!(convert_mode(Mode::TriangleFan, &mut indices, true), PrimitiveType::TriangleList);
+        assert_eq!(indices, vec![0, 2, 1, 0, 3, 2]);
+    }
+
+    #[test]
+    fn line_loop() {
+        let mut indices = vec![4, 5, 6];
+        assert_eq!(convert_mode(Mode::LineLoop, &mut indices, false), PrimitiveType::LineStrip);
+        assert_eq!(indices, vec![4, 5, 6, 4]);
+    }
+
+    #[test]
+    fn split_similarity() {
+        let (t, linear) = split_transform(&convert_matrix([
+            [0.0, 2.0, 0.0, 0.0], [-2.0, 0.0, 0.0, 0.0],
+            [0.0, 0.0, 2.0, 0.0], [1.0, 2.0, 3.0, 1.0]]));
+        assert_eq!(linear, IDENTITY);
+        assert_eq!(t.scale, 2.0);
+        assert_eq!((t.disp.x, t.disp.y, t.disp.z), (1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn split_mirror() {
+        let (t, linear) = split_transform(&convert_matrix([
+            [-1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]));
+        assert_eq!(linear, [[-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+        assert_eq!(t.scale, 1.0);
+    }
+}
@@ -0,0 +1,243 @@
+#![deny(missing_docs)]
+
+//! Asset loaders, producing entities and cameras ready to be drawn
+//! with `gfx_scene::Context`.
+
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate gfx;
+extern crate gfx_phase;
+extern crate gfx_scene;
+extern crate cgmath;
+extern crate collision;
+#[cfg(feature = "gltf")]
+extern crate gltf as gltf_lib;
+#[cfg(feature = "obj")]
+extern crate tobj;
+#[cfg(feature = "serialize")]
+extern crate serde;
+#[cfg(feature = "serialize")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "serialize")]
+extern crate ron;
+#[cfg(feature = "serialize")]
+extern crate serde_json;
+
+#[cfg(feature = "serialize")]
+pub mod desc;
+#[cfg(feature = "gltf")]
+pub mod gltf;
+#[cfg(feature = "obj")]
+pub mod obj;
+
+use std::io;
+use cgmath::{Matrix4, Ortho, PerspectiveFov, Rad};
+
+/// Transformation type of the loaded entities and cameras.
+pub type Transform = gfx_scene::camera::Transform<f32>;
+
+mod vertex {
+    #![allow(missing_docs)]
+    gfx_vertex!( Vertex {
+        a_Position@ pos: [f32; 3],
+        a_Normal@ normal: [f32; 3],
+        a_TexCoord@ tex_coord: [f32; 2],
+    });
+}
+
+/// Vertex format of the loaded meshes.
+pub use self::vertex::Vertex;
+
+/// Loading error.
+#[derive(Debug)]
+pub enum Error {
+    /// File system error.
+    Io(io::Error),
+    /// Error in parsing a glTF asset.
+    #[cfg(feature = "gltf")]
+    Gltf(gltf_lib::Error),
+    /// Error in parsing an OBJ model or its materials.
+    #[cfg(feature = "obj")]
+    Obj(tobj::LoadError),
+    /// Error in parsing a RON scene description.
+    #[cfg(feature = "serialize")]
+    RonParse(ron::error::SpannedError),
+    /// Error in writing a RON scene description.
+    #[cfg(feature = "serialize")]
+    Ron(ron::Error),
+    /// Error in reading or writing a JSON scene description.
+    #[cfg(feature = "serialize")]
+    Json(serde_json::Error),
+    /// The file format is not recognized.
+    UnknownFormat,
+    /// A node refers to a parent that doesn't precede it.
+    InvalidParent(usize),
+    /// A resource referenced by the scene can't be provided.
+    Resolve(String),
+    /// A required vertex attribute is missing.
+    MissingAttribute(&'static str),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+/// Alpha blending mode of a material.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    Opaque,
+    /// Pixels are discarded below the alpha cutoff.
+    Mask(f32),
+    /// Alpha blended.
+    Blend,
+}
+
+/// Generic metallic-roughness material.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(default))]
+pub struct Material {
+    /// Material name.
+    pub name: String,
+    /// Base color, RGBA.
+    pub base_color: [f32; 4],
+    /// Base color texture path, relative to the asset.
+    pub base_color_texture: Option<String>,
+    /// Metalness factor.
+    pub metallic: f32,
+    /// Roughness factor.
+    pub roughness: f32,
+    /// Emitted color.
+    pub emissive: [f32; 3],
+    /// Alpha blending mode.
+    pub alpha: AlphaMode,
+    /// Disable back face culling.
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            name: String::new(),
+            base_color: [1.0; 4],
+            base_color_texture: None,
+            metallic: 0.0,
+            roughness: 1.0,
+            emissive: [0.0; 3],
+            alpha: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+impl gfx_phase::Material for Material {}
+
+/// A loaded entity.
+#[derive(Clone, Debug)]
+pub struct Entity<R: gfx::Resources> {
+    /// Entity name.
+    pub name: String,
+    /// Vertex data.
+    pub mesh: gfx::Mesh<R>,
+    /// Drawable fragments, one per material group.
+    pub fragments: Vec<gfx_scene::Fragment<R, Material>>,
+    /// Local -> world transform.
+    pub transform: Transform,
+    /// Local bound.
+    pub bound: collision::Aabb3<f32>,
+    /// Visibility flag.
+    pub visible: bool,
+    /// Render layers.
+    pub layers: gfx_scene::Layers,
+    /// Whether it casts shadows.
+    pub shadow_caster: bool,
+    /// Whether it receives shadows.
+    pub shadow_receiver: bool,
+}
+
+impl<R: gfx::Resources> gfx_scene::Node for Entity<R> {
+    type Transform = Transform;
+    fn get_transform(&self) -> Transform {
+        self.transform.clone()
+    }
+}
+
+impl<R: gfx::Resources> gfx_scene::anim::Animated<f32> for Entity<R> {
+    fn get_transform_mut(&mut self) -> &mut Transform {
+        &mut self.transform
+    }
+}
+
+impl<R: gfx::Resources> gfx_scene::Entity<R, Material> for Entity<R> {
+    type Bound = collision::Aabb3<f32>;
+    fn is_visible(&self) -> bool {
+        self.visible
+    }
+    fn get_layers(&self) -> gfx_scene::Layers {
+        self.layers
+    }
+    fn is_shadow_caster(&self) -> bool {
+        self.shadow_caster
+    }
+    fn is_shadow_receiver(&self) -> bool {
+        self.shadow_receiver
+    }
+    fn get_bound(&self) -> collision::Aabb3<f32> {
+        self.bound.clone()
+    }
+    fn get_mesh(&self) -> &gfx::Mesh<R> {
+        &self.mesh
+    }
+    fn get_fragments(&self) -> &[gfx_scene::Fragment<R, Material>] {
+        &self.fragments
+    }
+}
+
+/// Projection of a loaded camera.
+#[derive(Clone, Debug)]
+pub enum Projection {
+    /// Perspective projection.
+    Perspective(PerspectiveFov<f32, Rad<f32>>),
+    /// Perspective projection with the far plane at infinity.
+    InfinitePerspective(gfx_scene::camera::InfinitePerspective<f32>),
+    /// Orthographic projection.
+    Orthographic(Ortho<f32>),
+}
+
+impl From<Projection> for Matrix4<f32> {
+    fn from(p: Projection) -> Matrix4<f32> {
+        match p {
+            Projection::Perspective(p) => p.into(),
+            Projection::InfinitePerspective(p) => p.into(),
+            Projection::Orthographic(p) => p.into(),
+        }
+    }
+}
+
+impl gfx_scene::camera::ClipDepth for Projection {
+    fn get_depth_range(&self) -> gfx_scene::camera::DepthRange {
+        use gfx_scene::camera::ClipDepth;
+        match *self {
+            Projection::Perspective(ref p) => p.get_depth_range(),
+            Projection::InfinitePerspective(ref p) => p.get_depth_range(),
+            Projection::Orthographic(ref p) => p.get_depth_range(),
+        }
+    }
+}
+
+/// A loaded camera.
+pub type Camera = gfx_scene::camera::Static<f32, Projection>;
+
+/// A loaded scene.
+pub struct Scene<R: gfx::Resources> {
+    /// Drawable entities, with the node hierarchy flattened.
+    pub entities: Vec<Entity<R>>,
+    /// Cameras.
+    pub cameras: Vec<Camera>,
+}
@@ -0,0 +1,143 @@
+//! Wavefront OBJ/MTL loader.
+
+use std::path::Path;
+use cgmath;
+use cgmath::Point3;
+use collision::Aabb3;
+use gfx;
+use gfx::traits::*;
+use gfx_scene;
+use tobj;
+use {AlphaMode, Entity, Error, Material, Vertex};
+
+impl From<tobj::LoadError> for Error {
+    fn from(e: tobj::LoadError) -> Error {
+        Error::Obj(e)
+    }
+}
+
+/// Convert an MTL material. The roughness is derived from the specular
+/// exponent, the emitted color is taken from the `Ke` statement.
+pub fn convert_material(mat: &tobj::Material) -> Material {
+    let diffuse = mat.diffuse.unwrap_or([1.0; 3]);
+    let alpha = mat.dissolve.unwrap_or(1.0);
+    let shininess = mat.shininess.unwrap_or(0.0).max(0.0);
+    let emissive = mat.unknown_param.get("Ke").map(|ke| {
+        let mut color = [0.0; 3];
+        for (c, v) in color.iter_mut().zip(ke.split_whitespace()) {
+            *c = v.parse().unwrap_or(0.0);
+        }
+        color
+    }).unwrap_or([0.0; 3]);
+    Material {
+        name: mat.name.clone(),
+        base_color: [diffuse[0], diffuse[1], diffuse[2], alpha],
+        base_color_texture: mat.diffuse_texture.clone(),
+        metallic: 0.0,
+        roughness: (2.0 / (shininess + 2.0)).sqrt(),
+        emissive: emissive,
+        alpha: if alpha < 1.0 {AlphaMode::Blend} else {AlphaMode::Opaque},
+        double_sided: false,
+    }
+}
+
+/// Load an OBJ model, along with its MTL libraries, from the local file
+/// system. All the objects of the file are merged into a single entity
+/// with the identity transform, having one fragment per material. Missing
+/// or broken MTL libraries are logged, and the default material is used.
+pub fn load<R, F, P>(factory: &mut F, path: P) -> Result<Entity<R>, Error> where
+    R: gfx::Resources,
+    F: gfx::Factory<R>,
+    P: AsRef<Path>,
+{
+    let options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        .. Default::default()
+    };
+    let name = path.as_ref().file_stem()
+                   .map(|s| s.to_string_lossy().into_owned())
+                   .unwrap_or(String::new());
+    let (models, materials) = try!(tobj::load_obj(path.as_ref(), &options));
+    let materials = match materials {
+        Ok(materials) => materials,
+        Err(e) => {
+            warn!("OBJ {}: failed to load the materials: {:?}", path.as_ref().display(), e);
+            Vec::new()
+        },
+    };
+
+    let mut vertices = Vec::new();
+    // indices grouped by the material, in the order of appearance
+    let mut groups: Vec<(Option<usize>, Vec<u32>)> = Vec::new();
+    for model in models.iter() {
+        let mesh = &model.mesh;
+        let base = vertices.len() as u32;
+        let count = mesh.positions.len() / 3;
+        for i in 0..count {
+            let p = &mesh.positions[i*3 .. i*3+3];
+            let n = if mesh.normals.len() >= i*3 + 3 {
+                [mesh.normals[i*3], mesh.normals[i*3+1], mesh.normals[i*3+2]]
+            } else {
+                [0.0, 0.0, 1.0]
+            };
+            let t = if mesh.texcoords.len() >= i*2 + 2 {
+                [mesh.texcoords[i*2], mesh.texcoords[i*2+1]]
+            } else {
+                [0.0, 0.0]
+            };
+            vertices.push(Vertex {
+                pos: [p[0], p[1], p[2]],
+                normal: n,
+                tex_coord: t,
+            });
+        }
+        let pos = match groups.iter().position(|g| g.0 == mesh.material_id) {
+            Some(pos) => pos,
+            None => {
+                groups.push((mesh.material_id, Vec::new()));
+                groups.len() - 1
+            },
+        };
+        groups[pos].1.extend(mesh.indices.iter().map(|i| base + i));
+    }
+
+    let bound = gfx_scene::bound::aabb_from_points(vertices.iter().map(|v|
+        Point3::new(v.pos[0], v.pos[1], v.pos[2])
+    )).unwrap_or(Aabb3::new(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, 0.0)));
+
+    let mut indices: Vec<u32> = Vec::new();
+    let mut ranges = Vec::new();
+    for (mat_id, group) in groups.into_iter() {
+        let start = indices.len() as u32;
+        indices.extend(group.into_iter());
+        let material = match mat_id.and_then(|id| materials.get(id)) {
+            Some(mat) => convert_material(mat),
+            None => Material::default(),
+        };
+        ranges.push((start, indices.len() as u32, material));
+    }
+
+    let mesh = factory.create_mesh(&vertices);
+    let ibuf = factory.create_buffer_index::<u32>(&indices);
+    let fragments = ranges.into_iter().map(|(start, end, material)| {
+        gfx_scene::Fragment::new(material, gfx::Slice {
+            start: start,
+            end: end,
+            prim_type: gfx::PrimitiveType::TriangleList,
+            kind: gfx::SliceKind::Index32(ibuf.clone(), 0),
+        })
+    }).collect();
+
+    Ok(Entity {
+        name: name,
+        mesh: mesh,
+        fragments: fragments,
+        transform: cgmath::Transform::one(),
+        bound: bound,
+        visible: true,
+        layers: gfx_scene::LAYER_DEFAULT,
+        shadow_caster: true,
+        shadow_receiver: true,
+    })
+}
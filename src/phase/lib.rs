@@ -15,8 +15,8 @@ mod phase;
 use std::fmt::Debug;
 use std::hash::Hash;
 
-pub use self::phase::{Object, sort, FlushError, OrderFun,
-                      AbstractPhase, CachedPhase, Phase};
+pub use self::phase::{Object, Prepared, sort, FlushError, OrderFun,
+                      AbstractPhase, SharedPhase, CachedPhase, Phase};
 
 /// Abstract material.
 pub trait Material {}
@@ -56,4 +56,8 @@ pub trait Technique<R: gfx::Resources, M: Material, V: ToDepth> {
     /// Fix the shader parameters, using an updated material and view info.
     /// Called every time before a batch is added to the draw queue.
     fn fix_params(&self, &M, &V, &mut Self::Params);
+    /// Fix the number of instances to draw, using an updated view info.
+    /// Called after `fix_params`, allowing the instance count to change
+    /// between frames. Keeps the compiled count by default.
+    fn fix_instances(&self, _: &V, _: &mut Option<gfx::InstanceCount>) {}
 }
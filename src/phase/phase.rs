@@ -20,6 +20,19 @@ pub trait AbstractPhase<R: gfx::Resources, M, V: ::ToDepth> {
              -> Result<(), FlushError>;
 }
 
+/// A phase that can prepare its objects through a shared reference,
+/// possibly from multiple threads, and merge them into the queue later.
+pub trait SharedPhase<R: gfx::Resources, M, V: ::ToDepth>: AbstractPhase<R, M, V> {
+    /// An object prepared for the queue.
+    type Prepared;
+    /// Prepare an entity for the queue, returns `None` if the phase
+    /// doesn't apply to it.
+    fn prepare(&self, &gfx::Mesh<R>, &gfx::Slice<R>, &M, &V)
+               -> Option<Self::Prepared>;
+    /// Add a prepared object to the queue.
+    fn merge(&mut self, Self::Prepared) -> Result<bool, gfx::batch::Error>;
+}
+
 /// A rendering object, encapsulating the batch and additional info
 /// needed for sorting. It is only exposed for this matter and
 /// accessed by immutable references by the user.
@@ -51,6 +64,13 @@ for Object<S, K, P> where
     }
 }
 
+/// An object prepared by the `Phase`, but not yet enqueued.
+pub struct Prepared<R: gfx::Resources, S, K, P: gfx::shade::ShaderParam> {
+    key: (K, gfx::Mesh<R>),
+    result: mem::MemResult<Object<S, K, P>>,
+    fresh: bool,
+}
+
 impl<'a, S, K, P: gfx::shade::ShaderParam> Object<S, K, P> {
     fn draw<X>(&self, stream: &mut X)
             -> Result<(), gfx::DrawError<gfx::batch::Error>> where
@@ -210,16 +230,16 @@ impl<
     Y: mem::Memory<(T::Kernel, gfx::Mesh<R>),
         Object<V::Depth, T::Kernel, T::Params>
     >,
->AbstractPhase<R, M, V> for Phase<R, M, V, T, Y> where
+> Phase<R, M, V, T, Y> where
     T::Params: Clone,
     <T::Params as gfx::shade::ShaderParam>::Link: Clone,
 {
-    fn enqueue(&mut self, orig_mesh: &gfx::Mesh<R>, slice: &gfx::Slice<R>,
-               material: &M, view_info: &V)
-               -> Result<bool, gfx::batch::Error> {
+    fn prepare_object(&self, orig_mesh: &gfx::Mesh<R>, slice: &gfx::Slice<R>,
+                      material: &M, view_info: &V)
+                      -> Option<Prepared<R, V::Depth, T::Kernel, T::Params>> {
         let kernel = match self.technique.test(orig_mesh, material) {
             Some(k) => k,
-            None => return Ok(false),
+            None => return None,
         };
         let depth = view_info.to_depth();
         let key = (kernel, orig_mesh.clone()); //TODO: avoid clone() here
@@ -230,10 +250,18 @@ impl<
                 o.depth = depth;
                 assert_eq!(o.kernel, kernel);
                 self.technique.fix_params(material, view_info, &mut o.params);
-                self.queue.objects.push(o);
-                return Ok(true)
+                self.technique.fix_instances(view_info, &mut o.instances);
+                return Some(Prepared {
+                    key: key,
+                    result: Ok(o),
+                    fresh: false,
+                })
             },
-            Some(Err(e)) => return Err(e),
+            Some(Err(e)) => return Some(Prepared {
+                key: key,
+                result: Err(e),
+                fresh: false,
+            }),
             None => ()
         }
         // Compile with the technique
@@ -241,7 +269,7 @@ impl<
             self.technique.compile(kernel);
         self.technique.fix_params(material, view_info, &mut params);
         let mut temp_mesh = gfx::Mesh::new(orig_mesh.num_vertices);
-        let (instances, mesh) = match instancing {
+        let (mut instances, mesh) = match instancing {
             Some((num, extra_attributes)) => {
                 temp_mesh.attributes.extend(orig_mesh.attributes.iter()
                     .chain(extra_attributes.iter()).map(|a| a.clone()));
@@ -249,6 +277,7 @@ impl<
             },
             None => (None, orig_mesh),
         };
+        self.technique.fix_instances(view_info, &mut instances);
         // Create queue object
         let object = gfx::batch::Core::new(mesh.clone(), program.clone())
             .map(|b| Object {
@@ -260,19 +289,79 @@ impl<
                 kernel: kernel,
                 state: *state
             });
+        Some(Prepared {
+            key: key,
+            result: object,
+            fresh: true,
+        })
+    }
+
+    fn push_prepared(&mut self, prepared: Prepared<R, V::Depth, T::Kernel, T::Params>)
+                     -> Result<bool, gfx::batch::Error> {
         // Remember and return
-        self.memory.store(key, object.clone());
-        match object {
+        if prepared.fresh {
+            self.memory.store(prepared.key, prepared.result.clone());
+        }
+        match prepared.result {
             Ok(o) => {
                 self.queue.objects.push(o);
                 Ok(true)
             },
             Err(e) => {
-                warn!("Phase {}: batch creation failed: {:?}", self.name, e);
+                if prepared.fresh {
+                    warn!("Phase {}: batch creation failed: {:?}", self.name, e);
+                }
                 Err(e)
             },
         }
     }
+}
+
+impl<
+    R: gfx::Resources,
+    M: ::Material,
+    V: ::ToDepth + Copy,
+    T: ::Technique<R, M, V>,
+    Y: mem::Memory<(T::Kernel, gfx::Mesh<R>),
+        Object<V::Depth, T::Kernel, T::Params>
+    >,
+> SharedPhase<R, M, V> for Phase<R, M, V, T, Y> where
+    T::Params: Clone,
+    <T::Params as gfx::shade::ShaderParam>::Link: Clone,
+{
+    type Prepared = Prepared<R, V::Depth, T::Kernel, T::Params>;
+
+    fn prepare(&self, mesh: &gfx::Mesh<R>, slice: &gfx::Slice<R>,
+               material: &M, view_info: &V) -> Option<Self::Prepared> {
+        self.prepare_object(mesh, slice, material, view_info)
+    }
+
+    fn merge(&mut self, prepared: Self::Prepared)
+             -> Result<bool, gfx::batch::Error> {
+        self.push_prepared(prepared)
+    }
+}
+
+impl<
+    R: gfx::Resources,
+    M: ::Material,
+    V: ::ToDepth + Copy,
+    T: ::Technique<R, M, V>,
+    Y: mem::Memory<(T::Kernel, gfx::Mesh<R>),
+        Object<V::Depth, T::Kernel, T::Params>
+    >,
+>AbstractPhase<R, M, V> for Phase<R, M, V, T, Y> where
+    T::Params: Clone,
+    <T::Params as gfx::shade::ShaderParam>::Link: Clone,
+{
+    fn enqueue(&mut self, orig_mesh: &gfx::Mesh<R>, slice: &gfx::Slice<R>,
+               material: &M, view_info: &V)
+               -> Result<bool, gfx::batch::Error> {
+        match self.prepare_object(orig_mesh, slice, material, view_info) {
+            Some(prepared) => self.push_prepared(prepared),
+            None => Ok(false),
+        }
+    }
 
     fn flush<S: gfx::Stream<R>>(&mut self, stream: &mut S)
              -> Result<(), FlushError> {
@@ -0,0 +1,394 @@
+//! Keyframe animation of node transforms.
+
+use std::cmp::Ordering;
+use cgmath;
+use cgmath::{Quaternion, Vector3};
+use camera::Transform;
+
+/// Interpolation between keyframes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Hold the value of the previous keyframe.
+    Step,
+    /// Linear interpolation, spherical for rotations.
+    Linear,
+    /// Cubic Hermite spline. Each keyframe is stored as a triplet of the
+    /// in-tangent, the value, and the out-tangent, like in glTF.
+    CubicSpline,
+}
+
+/// A value that can be animated.
+pub trait Keyframe<S>: Copy {
+    /// Interpolate linearly towards another value.
+    fn interpolate(&self, &Self, S) -> Self;
+    /// Compute the cubic Hermite curve point, given the start value, the
+    /// start tangent, the end value, the end tangent, and the basis weights.
+    fn cubic(&Self, &Self, &Self, &Self, [S; 4]) -> Self;
+}
+
+impl<S: cgmath::BaseFloat> Keyframe<S> for Vector3<S> {
+    fn interpolate(&self, other: &Vector3<S>, t: S) -> Vector3<S> {
+        use cgmath::Vector;
+        self.add_v(&other.sub_v(self).mul_s(t))
+    }
+    fn cubic(v0: &Vector3<S>, m0: &Vector3<S>, v1: &Vector3<S>, m1: &Vector3<S>,
+             w: [S; 4]) -> Vector3<S> {
+        use cgmath::Vector;
+        v0.mul_s(w[0]).add_v(&m0.mul_s(w[1]))
+          .add_v(&v1.mul_s(w[2])).add_v(&m1.mul_s(w[3]))
+    }
+}
+
+impl<S: cgmath::BaseFloat> Keyframe<S> for Quaternion<S> {
+    fn interpolate(&self, other: &Quaternion<S>, t: S) -> Quaternion<S> {
+        // take the shortest arc
+        let other = if self.dot(other) < S::zero() {
+            other.mul_s(-S::one())
+        } else {
+            *other
+        };
+        self.slerp(&other, t)
+    }
+    fn cubic(v0: &Quaternion<S>, m0: &Quaternion<S>, v1: &Quaternion<S>,
+             m1: &Quaternion<S>, w: [S; 4]) -> Quaternion<S> {
+        v0.mul_s(w[0]).add_q(&m0.mul_s(w[1]))
+          .add_q(&v1.mul_s(w[2])).add_q(&m1.mul_s(w[3]))
+          .normalize()
+    }
+}
+
+macro_rules! impl_keyframe_scalar {
+    ($($ty:ty),*) => {$(
+        impl Keyframe<$ty> for $ty {
+            fn interpolate(&self, other: &$ty, t: $ty) -> $ty {
+                *self + (*other - *self) * t
+            }
+            fn cubic(v0: &$ty, m0: &$ty, v1: &$ty, m1: &$ty, w: [$ty; 4]) -> $ty {
+                *v0 * w[0] + *m0 * w[1] + *v1 * w[2] + *m1 * w[3]
+            }
+        }
+    )*}
+}
+
+impl_keyframe_scalar!(f32, f64);
+
+/// Animation track of a single value.
+#[derive(Clone, Debug)]
+pub struct Track<S, T> {
+    /// Interpolation mode.
+    pub interpolation: Interpolation,
+    /// Keyframe times, ascending.
+    pub times: Vec<S>,
+    /// Keyframe values, three per keyframe for `CubicSpline`.
+    pub values: Vec<T>,
+}
+
+impl<S: cgmath::BaseFloat, T: Keyframe<S>> Track<S, T> {
+    /// Create a new track.
+    pub fn new(interpolation: Interpolation, times: Vec<S>, values: Vec<T>)
+               -> Track<S, T> {
+        debug_assert_eq!(values.len(), match interpolation {
+            Interpolation::CubicSpline => times.len() * 3,
+            _ => times.len(),
+        });
+        Track {
+            interpolation: interpolation,
+            times: times,
+            values: values,
+        }
+    }
+
+    /// Get the time of the last keyframe.
+    pub fn get_duration(&self) -> S {
+        self.times.last().cloned().unwrap_or(S::zero())
+    }
+
+    fn get_value(&self, key: usize) -> T {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.values[key * 3 + 1],
+            _ => self.values[key],
+        }
+    }
+
+    /// Sample the track at a given time, clamping it to the keyframe range.
+    /// Returns `None` if there are no keyframes.
+    pub fn sample(&self, time: S) -> Option<T> {
+        let count = self.times.len();
+        if count == 0 {
+            return None
+        }
+        // index of the first keyframe past the time
+        let next = match self.times.binary_search_by(|k|
+            k.partial_cmp(&time).unwrap_or(Ordering::Less)) {
+            Ok(i) => return Some(self.get_value(i)),
+            Err(i) => i,
+        };
+        if next == 0 {
+            return Some(self.get_value(0))
+        }
+        if next == count {
+            return Some(self.get_value(count - 1))
+        }
+        let prev = next - 1;
+        let dt = self.times[next] - self.times[prev];
+        let t = (time - self.times[prev]) / dt;
+        Some(match self.interpolation {
+            Interpolation::Step => self.values[prev],
+            Interpolation::Linear =>
+                self.values[prev].interpolate(&self.values[next], t),
+            Interpolation::CubicSpline => {
+                let one = S::one();
+                let two = one + one;
+                let three = two + one;
+                let t2 = t * t;
+                let t3 = t2 * t;
+                let w = [
+                    two * t3 - three * t2 + one,
+                    (t3 - two * t2 + t) * dt,
+                    three * t2 - two * t3,
+                    (t3 - t2) * dt,
+                ];
+                let v = &self.values;
+                T::cubic(&v[prev * 3 + 1], &v[prev * 3 + 2],
+                         &v[next * 3 + 1], &v[next * 3], w)
+            },
+        })
+    }
+}
+
+/// An animated node.
+pub trait Animated<S> {
+    /// Get the transform to be written by the animation.
+    fn get_transform_mut(&mut self) -> &mut Transform<S>;
+}
+
+impl<S> Animated<S> for Transform<S> {
+    fn get_transform_mut(&mut self) -> &mut Transform<S> {
+        self
+    }
+}
+
+impl<S, P> Animated<S> for ::camera::Static<S, P> {
+    fn get_transform_mut(&mut self) -> &mut Transform<S> {
+        &mut self.transform
+    }
+}
+
+/// Set of tracks animating a single node. Transform components without
+/// a track are left untouched.
+#[derive(Clone, Debug)]
+pub struct Channel<S> {
+    /// Index of the target node.
+    pub target: usize,
+    /// Translation track.
+    pub translation: Option<Track<S, Vector3<S>>>,
+    /// Rotation track.
+    pub rotation: Option<Track<S, Quaternion<S>>>,
+    /// Uniform scale track.
+    pub scale: Option<Track<S, S>>,
+}
+
+impl<S: cgmath::BaseFloat + Keyframe<S>> Channel<S> {
+    /// Create an empty channel for a given node.
+    pub fn new(target: usize) -> Channel<S> {
+        Channel {
+            target: target,
+            translation: None,
+            rotation: None,
+            scale: None,
+        }
+    }
+
+    /// Get the time of the last keyframe in any of the tracks.
+    pub fn get_duration(&self) -> S {
+        let zero = S::zero();
+        let t = self.translation.as_ref().map_or(zero, |t| t.get_duration());
+        let r = self.rotation.as_ref().map_or(zero, |t| t.get_duration());
+        let s = self.scale.as_ref().map_or(zero, |t| t.get_duration());
+        t.max(r).max(s)
+    }
+
+    /// Sample the tracks at a given time into a transform.
+    pub fn sample(&self, time: S, transform: &mut Transform<S>) {
+        if let Some(v) = self.translation.as_ref().and_then(|t| t.sample(time)) {
+            transform.disp = v;
+        }
+        if let Some(q) = self.rotation.as_ref().and_then(|t| t.sample(time)) {
+            transform.rot = q;
+        }
+        if let Some(s) = self.scale.as_ref().and_then(|t| t.sample(time)) {
+            transform.scale = s;
+        }
+    }
+}
+
+/// Animation clip, a named set of channels.
+#[derive(Clone, Debug)]
+pub struct Clip<S> {
+    /// Clip name.
+    pub name: String,
+    /// Channels, one per animated node.
+    pub channels: Vec<Channel<S>>,
+}
+
+impl<S: cgmath::BaseFloat + Keyframe<S>> Clip<S> {
+    /// Get the clip duration.
+    pub fn get_duration(&self) -> S {
+        self.channels.iter().fold(S::zero(), |d, c| d.max(c.get_duration()))
+    }
+
+    /// Write the transforms sampled at a given time into the nodes,
+    /// indexed by the channel targets. Targets out of range are skipped.
+    pub fn apply<A: Animated<S>>(&self, time: S, nodes: &mut [A]) {
+        for chan in self.channels.iter() {
+            if let Some(node) = nodes.get_mut(chan.target) {
+                chan.sample(time, node.get_transform_mut());
+            }
+        }
+    }
+}
+
+/// Playback state of a clip.
+#[derive(Clone, Copy, Debug)]
+pub struct Player<S> {
+    /// Current time within the clip.
+    pub time: S,
+    /// Playback speed multiplier.
+    pub speed: S,
+    /// Wrap around at the end of the clip, instead of stopping.
+    pub looping: bool,
+    /// Advance the time on updates.
+    pub playing: bool,
+}
+
+impl<S: cgmath::BaseFloat + Keyframe<S>> Player<S> {
+    /// Create a new player at the start, playing once at normal speed.
+    pub fn new() -> Player<S> {
+        Player {
+            time: S::zero(),
+            speed: S::one(),
+            looping: false,
+            playing: true,
+        }
+    }
+
+    /// Enable looping.
+    pub fn with_looping(self) -> Player<S> {
+        Player {
+            looping: true,
+            .. self
+        }
+    }
+
+    /// Advance the time by a step, wrapping or stopping at the clip ends.
+    pub fn advance(&mut self, delta: S, clip: &Clip<S>) {
+        if !self.playing {
+            return
+        }
+        let duration = clip.get_duration();
+        self.time = self.time + delta * self.speed;
+        if duration <= S::zero() {
+            self.time = S::zero();
+        } else if self.looping {
+            self.time = self.time % duration;
+            if self.time < S::zero() {
+                self.time = self.time + duration;
+            }
+        } else if self.time >= duration || self.time < S::zero() {
+            self.time = self.time.max(S::zero()).min(duration);
+            self.playing = false;
+        }
+    }
+
+    /// Advance the time and write the sampled transforms into the nodes.
+    /// To be called every frame before drawing.
+    pub fn update<A: Animated<S>>(&mut self, delta: S, clip: &Clip<S>, nodes: &mut [A]) {
+        self.advance(delta, clip);
+        clip.apply(self.time, nodes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath;
+    use cgmath::{Quaternion, Rad, Rotation3};
+    use camera::Transform;
+    use super::{Channel, Clip, Interpolation, Keyframe, Player, Track};
+
+    fn approx(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn sample_step_linear() {
+        let times = vec![0.0, 1.0, 3.0];
+        let values = vec![1.0f64, 3.0, 7.0];
+        let step = Track::new(Interpolation::Step, times.clone(), values.clone());
+        let linear = Track::new(Interpolation::Linear, times, values);
+        assert_eq!(step.sample(0.5), Some(1.0));
+        assert_eq!(step.sample(1.0), Some(3.0));
+        assert!(approx(linear.sample(0.5).unwrap(), 2.0));
+        assert!(approx(linear.sample(2.0).unwrap(), 5.0));
+        // clamped to the keyframe range
+        assert_eq!(linear.sample(-1.0), Some(1.0));
+        assert_eq!(linear.sample(10.0), Some(7.0));
+        assert_eq!(Track::<f64, f64>::new(Interpolation::Linear, vec![], vec![]).sample(0.0), None);
+    }
+
+    #[test]
+    fn sample_cubic() {
+        // in-tangent, value, out-tangent per keyframe
+        let track = Track::new(Interpolation::CubicSpline, vec![0.0, 2.0],
+                               vec![0.0f64, 1.0, 0.0, 0.0, 5.0, 0.0]);
+        assert!(approx(track.sample(0.0).unwrap(), 1.0));
+        assert!(approx(track.sample(2.0).unwrap(), 5.0));
+        // flat tangents: symmetric around the middle
+        assert!(approx(track.sample(1.0).unwrap(), 3.0));
+        // tangents matching the slope reproduce the line
+        let line = Track::new(Interpolation::CubicSpline, vec![0.0, 2.0],
+                              vec![2.0f64, 1.0, 2.0, 2.0, 5.0, 2.0]);
+        assert!(approx(line.sample(0.5).unwrap(), 2.0));
+    }
+
+    #[test]
+    fn slerp_shortest_arc() {
+        let a: Quaternion<f64> = Rotation3::from_angle_y(Rad { s: 0.0 });
+        let b: Quaternion<f64> = Rotation3::from_angle_y(Rad { s: 1.0 });
+        let half: Quaternion<f64> = Rotation3::from_angle_y(Rad { s: 0.5 });
+        let negated = b.mul_s(-1.0);
+        for q in [a.interpolate(&b, 0.5), a.interpolate(&negated, 0.5)].iter() {
+            assert!(approx(q.s, half.s) && approx(q.v.y, half.v.y), "{:?}", q);
+        }
+    }
+
+    fn clip() -> Clip<f64> {
+        let mut chan = Channel::new(0);
+        chan.scale = Some(Track::new(Interpolation::Linear, vec![0.0, 2.0], vec![1.0, 3.0]));
+        Clip {
+            name: "test".to_string(),
+            channels: vec![chan],
+        }
+    }
+
+    #[test]
+    fn player_looping() {
+        let clip = clip();
+        let mut player = Player::new().with_looping();
+        player.advance(3.0, &clip);
+        assert!(approx(player.time, 1.0) && player.playing);
+        player.speed = -1.0;
+        player.advance(1.5, &clip);
+        assert!(approx(player.time, 1.5));
+    }
+
+    #[test]
+    fn player_once() {
+        let clip = clip();
+        let mut player = Player::new();
+        player.advance(3.0, &clip);
+        assert!(approx(player.time, 2.0) && !player.playing);
+        let mut nodes: Vec<Transform<f64>> = vec![cgmath::Transform::one()];
+        clip.apply(1.0, &mut nodes);
+        assert!(approx(nodes[0].scale, 2.0));
+    }
+}
@@ -0,0 +1,227 @@
+//! Batched frustum culling of axis-aligned boxes.
+
+use cgmath;
+use collision::{Aabb3, Relation};
+use camera::DepthRange;
+use cull::Culler;
+
+/// Number of bounds processed at once, enough to fill 256-bit registers.
+const LANES: usize = 8;
+
+type Lane = [f32; LANES];
+
+/// Clip space plane, defined as `k * row[3] + sign * row[axis]`.
+type PlaneDef = (usize, f32, f32);
+
+fn get_plane_defs(depth: DepthRange) -> [PlaneDef; 6] {
+    let (near, far) = match depth {
+        DepthRange::NegativeOneToOne => ((2, 1.0, 1.0), (2, 1.0, -1.0)),
+        DepthRange::ZeroToOne => ((2, 0.0, 1.0), (2, 1.0, -1.0)),
+        DepthRange::Reversed => ((2, 1.0, -1.0), (2, 0.0, 1.0)),
+    };
+    [(0, 1.0, 1.0), (0, 1.0, -1.0),
+     (1, 1.0, 1.0), (1, 1.0, -1.0),
+     near, far]
+}
+
+/// Frustum culler of `Aabb3<f32>` bounds, optimized for batches.
+/// `cull_many` transposes the boxes (as centers and extents) and their
+/// matrices into a structure-of-arrays layout, testing groups of 8 bounds
+/// against the clip planes in lock-step. There are no explicit SIMD
+/// intrinsics: the code is portable, and the fixed-size lane loops are
+/// left for the compiler to vectorize, with the plane results accumulated
+/// in bit masks and no branches per lane. Only `cull_many` benefits, which
+/// is what the drawing loops of `Context` and `RelativeContext` call.
+/// A single `cull` evaluates its bound directly, at the cost of a scalar
+/// test.
+#[derive(Clone, Debug)]
+pub struct BatchFrustum {
+    planes: [PlaneDef; 6],
+    rows: [[Lane; 4]; 4],
+    center: [Lane; 3],
+    extent: [Lane; 3],
+}
+
+impl BatchFrustum {
+    /// Create a new batch culler, assuming the standard GL depth range.
+    pub fn new() -> BatchFrustum {
+        BatchFrustum::with_depth(DepthRange::NegativeOneToOne)
+    }
+
+    /// Create a new batch culler for a given clip space depth convention.
+    /// Planes at infinity never reject anything.
+    pub fn with_depth(depth: DepthRange) -> BatchFrustum {
+        BatchFrustum {
+            planes: get_plane_defs(depth),
+            rows: [[[0.0; LANES]; 4]; 4],
+            center: [[0.0; LANES]; 3],
+            extent: [[0.0; LANES]; 3],
+        }
+    }
+
+    fn load(&mut self, lane: usize, bound: &Aabb3<f32>, mvp: &cgmath::Matrix4<f32>) {
+        for r in 0..4 {
+            for c in 0..4 {
+                self.rows[r][c][lane] = mvp[c][r];
+            }
+        }
+        self.center[0][lane] = (bound.min.x + bound.max.x) * 0.5;
+        self.center[1][lane] = (bound.min.y + bound.max.y) * 0.5;
+        self.center[2][lane] = (bound.min.z + bound.max.z) * 0.5;
+        self.extent[0][lane] = (bound.max.x - bound.min.x) * 0.5;
+        self.extent[1][lane] = (bound.max.y - bound.min.y) * 0.5;
+        self.extent[2][lane] = (bound.max.z - bound.min.z) * 0.5;
+    }
+
+    fn relate(&self) -> [Relation; LANES] {
+        let mut outside = [0u8; LANES];
+        let mut inside = [1u8; LANES];
+        for &(axis, k, sign) in self.planes.iter() {
+            let (w, v) = (&self.rows[3], &self.rows[axis]);
+            for l in 0..LANES {
+                let a = k * w[0][l] + sign * v[0][l];
+                let b = k * w[1][l] + sign * v[1][l];
+                let c = k * w[2][l] + sign * v[2][l];
+                let d = k * w[3][l] + sign * v[3][l];
+                let dist = a * self.center[0][l] + b * self.center[1][l] +
+                           c * self.center[2][l] + d;
+                let radius = a.abs() * self.extent[0][l] +
+                             b.abs() * self.extent[1][l] +
+                             c.abs() * self.extent[2][l];
+                outside[l] |= (dist < -radius) as u8;
+                inside[l] &= (dist >= radius) as u8;
+            }
+        }
+        // indexed by `outside * 2 + inside`
+        const TABLE: [Relation; 4] = [Relation::Cross, Relation::In, Relation::Out, Relation::Out];
+        let mut result = [Relation::Cross; LANES];
+        for l in 0..LANES {
+            result[l] = TABLE[(outside[l] * 2 + inside[l]) as usize];
+        }
+        result
+    }
+}
+
+impl Culler<f32, Aabb3<f32>> for BatchFrustum {
+    fn init(&mut self) {}
+
+    fn set_depth_range(&mut self, depth: DepthRange) {
+        self.planes = get_plane_defs(depth);
+    }
+
+    fn cull(&mut self, bound: &Aabb3<f32>, mvp: &cgmath::Matrix4<f32>) -> Relation {
+        let center = [(bound.min.x + bound.max.x) * 0.5,
+                      (bound.min.y + bound.max.y) * 0.5,
+                      (bound.min.z + bound.max.z) * 0.5];
+        let extent = [(bound.max.x - bound.min.x) * 0.5,
+                      (bound.max.y - bound.min.y) * 0.5,
+                      (bound.max.z - bound.min.z) * 0.5];
+        let mut inside = true;
+        for &(axis, k, sign) in self.planes.iter() {
+            let row = |c: usize| k * mvp[c][3] + sign * mvp[c][axis];
+            let (a, b, c, d) = (row(0), row(1), row(2), row(3));
+            let dist = a * center[0] + b * center[1] + c * center[2] + d;
+            let radius = a.abs() * extent[0] + b.abs() * extent[1] + c.abs() * extent[2];
+            if dist < -radius {
+                return Relation::Out
+            }
+            inside = inside && dist >= radius;
+        }
+        if inside {Relation::In} else {Relation::Cross}
+    }
+
+    fn cull_many(&mut self, bounds: &[Aabb3<f32>], mvps: &[cgmath::Matrix4<f32>],
+                 out: &mut Vec<Relation>) {
+        let num = if bounds.len() < mvps.len() {bounds.len()} else {mvps.len()};
+        let mut start = 0;
+        while start < num {
+            let count = if num - start < LANES {num - start} else {LANES};
+            // the lanes past `count` keep stale data, their results are dropped
+            for l in 0..count {
+                self.load(l, &bounds[start + l], &mvps[start + l]);
+            }
+            let relations = self.relate();
+            out.extend(relations[..count].iter().cloned());
+            start += count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Matrix, Matrix4, Point3};
+    use collision::{Aabb3, Relation};
+    use camera::DepthRange;
+    use cull::{Culler, Frustum};
+    use super::BatchFrustum;
+
+    /// Perspective projection with a 90 degree field of view.
+    fn projection(depth: DepthRange) -> Matrix4<f32> {
+        let (n, f) = (1.0f32, 50.0f32);
+        let (a, b) = match depth {
+            DepthRange::NegativeOneToOne => ((f + n) / (n - f), 2.0 * f * n / (n - f)),
+            DepthRange::ZeroToOne => (f / (n - f), f * n / (n - f)),
+            DepthRange::Reversed => (n / (f - n), f * n / (f - n)),
+        };
+        Matrix4::new(1.0, 0.0, 0.0, 0.0,
+                     0.0, 1.0, 0.0, 0.0,
+                     0.0, 0.0, a, -1.0,
+                     0.0, 0.0, b, 0.0)
+    }
+
+    /// Rotation around the Y axis.
+    fn rotation(angle: f32) -> Matrix4<f32> {
+        let (s, c) = angle.sin_cos();
+        Matrix4::new(c, 0.0, -s, 0.0,
+                     0.0, 1.0, 0.0, 0.0,
+                     s, 0.0, c, 0.0,
+                     0.0, 0.0, 0.0, 1.0)
+    }
+
+    /// Deterministic boxes and matrices around the view.
+    fn generate(depth: DepthRange, num: usize) -> (Vec<Aabb3<f32>>, Vec<Matrix4<f32>>) {
+        let mut seed = 12345u32;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 8) as f32 / (1u32 << 24) as f32
+        };
+        let proj = projection(depth);
+        let mut bounds = Vec::new();
+        let mut mvps = Vec::new();
+        for _ in 0..num {
+            let c = [random() * 80.0 - 40.0, random() * 80.0 - 40.0, random() * -80.0 + 20.0];
+            let e = [random() * 3.0 + 0.1, random() * 3.0 + 0.1, random() * 3.0 + 0.1];
+            bounds.push(Aabb3::new(Point3::new(c[0] - e[0], c[1] - e[1], c[2] - e[2]),
+                                   Point3::new(c[0] + e[0], c[1] + e[1], c[2] + e[2])));
+            mvps.push(proj.mul_m(&rotation(random() * 0.5 - 0.25)));
+        }
+        (bounds, mvps)
+    }
+
+    #[test]
+    fn matches_frustum() {
+        for &depth in [DepthRange::NegativeOneToOne,
+                       DepthRange::ZeroToOne,
+                       DepthRange::Reversed].iter() {
+            // not a multiple of the lane count, to cover a partial group
+            let (bounds, mvps) = generate(depth, 1000 + 3);
+            let mut batch = BatchFrustum::with_depth(depth);
+            let mut relations = Vec::new();
+            batch.cull_many(&bounds, &mvps, &mut relations);
+            assert_eq!(relations.len(), bounds.len());
+            let mut frustum = Frustum::with_depth(depth);
+            let mut counts = [0; 3];
+            for ((bound, mvp), &relation) in bounds.iter().zip(mvps.iter()).zip(relations.iter()) {
+                let expected = frustum.cull(bound, mvp);
+                assert_eq!(relation, expected, "{:?} for {:?} under {:?}", relation, bound, depth);
+                assert_eq!(batch.cull(bound, mvp), expected);
+                counts[match expected {
+                    Relation::In => 0,
+                    Relation::Cross => 1,
+                    Relation::Out => 2,
+                }] += 1;
+            }
+            assert!(counts.iter().all(|&c| c > 0), "{:?} under {:?}", counts, depth);
+        }
+    }
+}
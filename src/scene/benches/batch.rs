@@ -0,0 +1,56 @@
+#![feature(test)]
+
+extern crate test;
+extern crate cgmath;
+extern crate collision;
+extern crate gfx_scene;
+
+use cgmath::{Matrix4, Point3};
+use collision::{Aabb3, Relation};
+use gfx_scene::{BatchFrustum, Culler, Frustum};
+use test::Bencher;
+
+const NUM: usize = 1024;
+
+fn generate() -> (Vec<Aabb3<f32>>, Vec<Matrix4<f32>>) {
+    let (n, f) = (1.0f32, 50.0f32);
+    let proj = Matrix4::new(1.0, 0.0, 0.0, 0.0,
+                            0.0, 1.0, 0.0, 0.0,
+                            0.0, 0.0, (f + n) / (n - f), -1.0,
+                            0.0, 0.0, 2.0 * f * n / (n - f), 0.0);
+    let mut seed = 12345u32;
+    let mut random = move || {
+        seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        (seed >> 8) as f32 / (1u32 << 24) as f32
+    };
+    let bounds = (0..NUM).map(|_| {
+        let c = [random() * 80.0 - 40.0, random() * 80.0 - 40.0, random() * -80.0 + 20.0];
+        Aabb3::new(Point3::new(c[0] - 1.0, c[1] - 1.0, c[2] - 1.0),
+                   Point3::new(c[0] + 1.0, c[1] + 1.0, c[2] + 1.0))
+    }).collect();
+    (bounds, vec![proj; NUM])
+}
+
+#[bench]
+fn frustum_cull(b: &mut Bencher) {
+    let (bounds, mvps) = generate();
+    let mut culler = Frustum::new();
+    let mut out: Vec<Relation> = Vec::with_capacity(NUM);
+    b.iter(|| {
+        out.clear();
+        culler.cull_many(&bounds, &mvps, &mut out);
+        test::black_box(&out);
+    });
+}
+
+#[bench]
+fn batch_frustum_cull(b: &mut Bencher) {
+    let (bounds, mvps) = generate();
+    let mut culler = BatchFrustum::new();
+    let mut out: Vec<Relation> = Vec::with_capacity(NUM);
+    b.iter(|| {
+        out.clear();
+        culler.cull_many(&bounds, &mvps, &mut out);
+        test::black_box(&out);
+    });
+}
@@ -0,0 +1,284 @@
+//! Bound computation from vertex data and bound transformation.
+
+use cgmath;
+use cgmath::{Point3, Vector3};
+use collision::{Aabb3, Sphere};
+use gfx;
+use gfx::attrib::{FloatSize, Format, IntSize, IntSubType, SignFlag, Type};
+
+/// Compute the tight box around points.
+pub fn aabb_from_points<S, I>(mut points: I) -> Option<Aabb3<S>> where
+    S: cgmath::BaseFloat,
+    I: Iterator<Item = Point3<S>>,
+{
+    let first = match points.next() {
+        Some(p) => p,
+        None => return None,
+    };
+    let (min, max) = points.fold((first, first), |(min, max), p| (
+        Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+        Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+    ));
+    Some(Aabb3::new(min, max))
+}
+
+/// Compute a bounding sphere of points, using Ritter's algorithm.
+/// The result is not minimal, but typically within a few percent of it.
+pub fn sphere_from_points<S: cgmath::BaseFloat>(points: &[Point3<S>]) -> Option<Sphere<S>> {
+    use cgmath::{EuclideanVector, Point, Vector};
+    let first = match points.first() {
+        Some(p) => *p,
+        None => return None,
+    };
+    let farthest = |from: &Point3<S>| points.iter().fold(first, |best, p| {
+        if p.sub_p(from).length2() > best.sub_p(from).length2() {*p} else {best}
+    });
+    let a = farthest(&first);
+    let b = farthest(&a);
+    let two = S::one() + S::one();
+    let mut center = a.add_v(&b.sub_p(&a).div_s(two));
+    let mut radius = b.sub_p(&a).length() / two;
+    for p in points.iter() {
+        let offset = p.sub_p(&center);
+        let dist = offset.length();
+        if dist > radius {
+            // grow to enclose the point, keeping the opposite side
+            let new_radius = (radius + dist) / two;
+            center = center.add_v(&offset.mul_s((new_radius - radius) / dist));
+            radius = new_radius;
+        }
+    }
+    Some(Sphere {
+        center: center,
+        radius: radius,
+    })
+}
+
+/// Error in reading the vertex positions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReadError {
+    /// The attribute format is not supported.
+    UnsupportedFormat(Type),
+    /// The attribute has less than 2 or more than 4 components.
+    UnsupportedCount(u8),
+    /// The data is too short for the vertex count.
+    OutOfBounds,
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    (data[0] as u32) | (data[1] as u32) << 8 |
+    (data[2] as u32) << 16 | (data[3] as u32) << 24
+}
+
+/// Read the positions of `count` vertices from raw little-endian vertex
+/// data, given the format of the position attribute. Two-component
+/// positions get zero Z. Integer formats are converted as is, or
+/// normalized if the format says so.
+pub fn read_positions(data: &[u8], format: &Format, count: usize)
+                      -> Result<Vec<Point3<f32>>, ReadError> {
+    let comp_size = match format.elem_type {
+        Type::Float(_, FloatSize::F32) => 4,
+        Type::Int(_, IntSize::U8, _) => 1,
+        Type::Int(_, IntSize::U16, _) => 2,
+        Type::Int(_, IntSize::U32, _) => 4,
+        other => return Err(ReadError::UnsupportedFormat(other)),
+    };
+    let num = format.elem_count as usize;
+    if num < 2 || num > 4 {
+        return Err(ReadError::UnsupportedCount(format.elem_count))
+    }
+    let stride = match format.stride as usize {
+        0 => comp_size * num,
+        s => s,
+    };
+    let offset = format.offset as usize;
+    if count > 0 && offset + stride * (count - 1) + comp_size * num > data.len() {
+        return Err(ReadError::OutOfBounds)
+    }
+    let read = |at: usize| -> f32 {
+        let bytes = &data[at..];
+        match format.elem_type {
+            Type::Float(..) => f32::from_bits(read_u32(bytes)),
+            Type::Int(sub, size, sign) => {
+                let (value, max) = match (size, sign) {
+                    (IntSize::U8, SignFlag::Signed) => (bytes[0] as i8 as f32, 127.0),
+                    (IntSize::U8, SignFlag::Unsigned) => (bytes[0] as f32, 255.0),
+                    (IntSize::U16, SignFlag::Signed) =>
+                        (((bytes[0] as u16) | (bytes[1] as u16) << 8) as i16 as f32, 32767.0),
+                    (IntSize::U16, SignFlag::Unsigned) =>
+                        (((bytes[0] as u16) | (bytes[1] as u16) << 8) as f32, 65535.0),
+                    (IntSize::U32, SignFlag::Signed) =>
+                        (read_u32(bytes) as i32 as f32, 2147483647.0),
+                    (IntSize::U32, SignFlag::Unsigned) =>
+                        (read_u32(bytes) as f32, 4294967295.0),
+                };
+                match sub {
+                    IntSubType::Normalized => (value / max).max(-1.0),
+                    _ => value,
+                }
+            },
+            _ => unreachable!(),
+        }
+    };
+    Ok((0..count).map(|i| {
+        let base = offset + i * stride;
+        Point3::new(read(base), read(base + comp_size),
+                    if num > 2 {read(base + 2 * comp_size)} else {0.0})
+    }).collect())
+}
+
+/// Compute the local box of a mesh position attribute.
+pub fn aabb_from_vertices(data: &[u8], format: &Format, count: usize)
+                          -> Result<Option<Aabb3<f32>>, ReadError> {
+    read_positions(data, format, count).map(|p| aabb_from_points(p.into_iter()))
+}
+
+/// Compute the local bounding sphere of a mesh position attribute.
+pub fn sphere_from_vertices(data: &[u8], format: &Format, count: usize)
+                            -> Result<Option<Sphere<f32>>, ReadError> {
+    read_positions(data, format, count).map(|p| sphere_from_points(&p))
+}
+
+/// Get the largest scale of a linear transform, given the images of the
+/// basis vectors. This is the largest singular value, i.e. the longest
+/// image of a unit vector, which may exceed the longest basis image when
+/// a non-uniform scale follows a rotation.
+pub fn get_max_scale<S: cgmath::BaseFloat>(x: &Vector3<S>, y: &Vector3<S>, z: &Vector3<S>) -> S {
+    use cgmath::Vector;
+    // the largest eigenvalue of the symmetric matrix M^T * M
+    let (g00, g11, g22) = (x.dot(x), y.dot(y), z.dot(z));
+    let (g01, g02, g12) = (x.dot(y), x.dot(z), y.dot(z));
+    let column = g00.max(g11).max(g22);
+    let p1 = g01 * g01 + g02 * g02 + g12 * g12;
+    let eigen = if p1 <= S::zero() {
+        column
+    } else {
+        let (one, two) = (S::one(), S::one() + S::one());
+        let three = two + one;
+        let q = (g00 + g11 + g22) / three;
+        let p2 = (g00 - q) * (g00 - q) + (g11 - q) * (g11 - q) +
+                 (g22 - q) * (g22 - q) + two * p1;
+        let p = (p2 / (three + three)).sqrt();
+        let (b00, b11, b22) = ((g00 - q) / p, (g11 - q) / p, (g22 - q) / p);
+        let (b01, b02, b12) = (g01 / p, g02 / p, g12 / p);
+        let det = b00 * (b11 * b22 - b12 * b12) -
+                  b01 * (b01 * b22 - b12 * b02) +
+                  b02 * (b01 * b12 - b11 * b02);
+        let r = (det / two).max(-one).min(one);
+        q + two * p * (r.acos() / three).cos()
+    };
+    // the basis images never exceed it, guarding against the rounding
+    eigen.max(column).sqrt()
+}
+
+/// A bound that can be moved into another space, e.g. from local
+/// to world, enclosing the transformed original.
+pub trait Transformable<S> {
+    /// Transform the bound.
+    fn transform<T: cgmath::Transform3<S>>(&self, &T) -> Self;
+}
+
+impl<S: cgmath::BaseFloat> Transformable<S> for Aabb3<S> {
+    fn transform<T: cgmath::Transform3<S>>(&self, t: &T) -> Aabb3<S> {
+        let corners = (0..8).map(|i| t.transform_point(&Point3::new(
+            if i & 1 == 0 {self.min.x} else {self.max.x},
+            if i & 2 == 0 {self.min.y} else {self.max.y},
+            if i & 4 == 0 {self.min.z} else {self.max.z})));
+        aabb_from_points(corners).unwrap()
+    }
+}
+
+impl<S: cgmath::BaseFloat> Transformable<S> for Sphere<S> {
+    fn transform<T: cgmath::Transform3<S>>(&self, t: &T) -> Sphere<S> {
+        let (zero, one) = (S::zero(), S::one());
+        let scale = get_max_scale(&t.transform_vector(&Vector3::new(one, zero, zero)),
+                                  &t.transform_vector(&Vector3::new(zero, one, zero)),
+                                  &t.transform_vector(&Vector3::new(zero, zero, one)));
+        Sphere {
+            center: t.transform_point(&self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// Get the world space bound of an entity.
+pub fn get_world_bound<R, M, S, E>(entity: &E) -> E::Bound where
+    R: gfx::Resources,
+    S: cgmath::BaseFloat,
+    E: ::Entity<R, M>,
+    E::Bound: Transformable<S>,
+    E::Transform: cgmath::Transform3<S>,
+{
+    entity.get_bound().transform(&entity.get_transform())
+}
+
+/// A bound that can be enclosed by a sphere.
+pub trait ToSphere<S> {
+    /// Get the enclosing sphere.
+    fn to_sphere(&self) -> Sphere<S>;
+}
+
+impl<S: cgmath::BaseFloat> ToSphere<S> for Aabb3<S> {
+    fn to_sphere(&self) -> Sphere<S> {
+        use cgmath::{EuclideanVector, Point, Vector};
+        let half = self.max.sub_p(&self.min).div_s(S::one() + S::one());
+        Sphere {
+            center: self.min.add_v(&half),
+            radius: half.length(),
+        }
+    }
+}
+
+impl<S: cgmath::BaseFloat> ToSphere<S> for Sphere<S> {
+    fn to_sphere(&self) -> Sphere<S> {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{EuclideanVector, Vector, Vector3};
+    use super::get_max_scale;
+
+    fn approx(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn max_scale_identity() {
+        let s = get_max_scale(&Vector3::unit_x(), &Vector3::unit_y(), &Vector3::unit_z());
+        assert!(approx(s, 1.0));
+    }
+
+    #[test]
+    fn max_scale_rotated_non_uniform() {
+        // diag(2, 1, 1) * Rz(45)
+        let (c, s) = (0.5f32.sqrt(), 0.5f32.sqrt());
+        let x = Vector3::new(2.0 * c, s, 0.0);
+        let y = Vector3::new(-2.0 * s, c, 0.0);
+        let z = Vector3::new(0.0, 0.0, 1.0);
+        let scale = get_max_scale(&x, &y, &z);
+        assert!(approx(scale, 2.0), "got {}", scale);
+        // the longest basis image underestimates it
+        assert!(x.length().max(y.length()) < 1.9);
+        // the image of the unit vector along the rotated X axis is that long
+        let v = x.mul_s(c).sub_v(&y.mul_s(s));
+        assert!(approx(v.length(), 2.0));
+    }
+
+    #[test]
+    fn max_scale_uniform() {
+        let s = get_max_scale(&Vector3::new(0.0, 3.0, 0.0),
+                              &Vector3::new(-3.0, 0.0, 0.0),
+                              &Vector3::new(0.0, 0.0, 3.0));
+        assert!(approx(s, 3.0));
+    }
+
+    #[test]
+    fn max_scale_degenerate() {
+        let s = get_max_scale(&Vector3::new(1.0, 1.0, 0.0),
+                              &Vector3::new(1.0, 1.0, 0.0),
+                              &Vector3::new(0.0, 0.0, 0.0));
+        assert!(approx(s, 2.0), "got {}", s);
+    }
+}
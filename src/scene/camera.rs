@@ -0,0 +1,352 @@
+//! Stock camera implementations.
+
+use cgmath;
+use cgmath::{Decomposed, Matrix3, Ortho, PerspectiveFov, Point3, Quaternion, Rad, Vector3};
+
+/// Decomposed transformation of the stock cameras and nodes.
+pub type Transform<S> = Decomposed<S, Vector3<S>, Quaternion<S>>;
+
+/// A camera with a directly controlled transformation.
+#[derive(Clone, Debug)]
+pub struct Static<S, P> {
+    /// Camera -> world transform.
+    pub transform: Transform<S>,
+    /// Projection.
+    pub projection: P,
+}
+
+/// Perspective camera.
+pub type Perspective<S> = Static<S, PerspectiveFov<S, Rad<S>>>;
+/// Orthographic camera.
+pub type Orthographic<S> = Static<S, Ortho<S>>;
+
+impl<S: cgmath::BaseFloat, P> Static<S, P> {
+    /// Create a new camera with an identity transform.
+    pub fn new(projection: P) -> Static<S, P> {
+        Static {
+            transform: cgmath::Transform::one(),
+            projection: projection,
+        }
+    }
+
+    /// Place the camera at `eye`, looking at `target`.
+    pub fn look_at(&mut self, eye: &Point3<S>, target: &Point3<S>, up: &Vector3<S>) {
+        use cgmath::{EuclideanVector, Point};
+        // the camera is looking at -Z
+        let back = eye.sub_p(target).normalize();
+        let right = up.cross(&back).normalize();
+        let up = back.cross(&right);
+        self.transform = Decomposed {
+            scale: S::one(),
+            rot: Quaternion::from(Matrix3::from_cols(right, up, back)),
+            disp: eye.to_vec(),
+        };
+    }
+}
+
+impl<S: cgmath::BaseFloat, P> ::Node for Static<S, P> {
+    type Transform = Transform<S>;
+    fn get_transform(&self) -> Transform<S> {
+        self.transform.clone()
+    }
+}
+
+impl<S: cgmath::BaseFloat, P> ::Camera<S> for Static<S, P> where
+    P: Clone + Into<cgmath::Matrix4<S>> + ClipDepth,
+{
+    type Projection = P;
+    fn get_projection(&self) -> P {
+        self.projection.clone()
+    }
+    fn get_depth_range(&self) -> DepthRange {
+        self.projection.get_depth_range()
+    }
+}
+
+fn yaw_pitch<S: cgmath::BaseFloat>(yaw: Rad<S>, pitch: Rad<S>) -> Quaternion<S> {
+    use cgmath::Rotation3;
+    let qy: Quaternion<S> = Rotation3::from_angle_y(yaw);
+    let qx: Quaternion<S> = Rotation3::from_angle_x(pitch);
+    qy.mul_q(&qx)
+}
+
+fn clamp_pitch<S: cgmath::BaseFloat>(pitch: S) -> S {
+    let limit = S::from(1.55).unwrap();
+    pitch.max(-limit).min(limit)
+}
+
+/// A camera orbiting around a target point, e.g. for model viewers.
+/// The yaw rotates around the vertical (Y) axis, the pitch lifts the
+/// camera above the horizon.
+#[derive(Clone, Debug)]
+pub struct Orbit<S, P = PerspectiveFov<S, Rad<S>>> {
+    /// Point to look at.
+    pub target: Point3<S>,
+    /// Distance from the target.
+    pub distance: S,
+    /// Rotation around the vertical axis.
+    pub yaw: Rad<S>,
+    /// Elevation angle.
+    pub pitch: Rad<S>,
+    /// Projection.
+    pub projection: P,
+}
+
+impl<S: cgmath::BaseFloat, P> Orbit<S, P> {
+    /// Create a new orbit camera.
+    pub fn new(target: Point3<S>, distance: S, projection: P) -> Orbit<S, P> {
+        Orbit {
+            target: target,
+            distance: distance,
+            yaw: Rad { s: S::zero() },
+            pitch: Rad { s: S::zero() },
+            projection: projection,
+        }
+    }
+
+    fn get_rotation(&self) -> Quaternion<S> {
+        // looking down at the target when elevated
+        yaw_pitch(self.yaw, Rad { s: -self.pitch.s })
+    }
+
+    /// Rotate around the target. The pitch is clamped short of the poles.
+    pub fn rotate(&mut self, yaw: Rad<S>, pitch: Rad<S>) {
+        self.yaw = Rad { s: self.yaw.s + yaw.s };
+        self.pitch = Rad { s: clamp_pitch(self.pitch.s + pitch.s) };
+    }
+
+    /// Multiply the distance to the target by a given factor.
+    pub fn zoom(&mut self, factor: S) {
+        let min = S::from(1.0e-3).unwrap();
+        self.distance = (self.distance * factor).max(min);
+    }
+
+    /// Move the target in the view plane, by amounts relative to the distance.
+    pub fn pan(&mut self, right: S, up: S) {
+        use cgmath::{Point, Rotation, Vector};
+        let rot = self.get_rotation();
+        let zero = S::zero();
+        let offset = rot.rotate_vector(&Vector3::new(right, up, zero));
+        self.target = self.target.add_v(&offset.mul_s(self.distance));
+    }
+}
+
+impl<S: cgmath::BaseFloat, P> ::Node for Orbit<S, P> {
+    type Transform = Transform<S>;
+    fn get_transform(&self) -> Transform<S> {
+        use cgmath::{Point, Rotation, Vector};
+        let rot = self.get_rotation();
+        let zero = S::zero();
+        let back = rot.rotate_vector(&Vector3::new(zero, zero, self.distance));
+        Decomposed {
+            scale: S::one(),
+            rot: rot,
+            disp: self.target.to_vec().add_v(&back),
+        }
+    }
+}
+
+impl<S: cgmath::BaseFloat, P> ::Camera<S> for Orbit<S, P> where
+    P: Clone + Into<cgmath::Matrix4<S>> + ClipDepth,
+{
+    type Projection = P;
+    fn get_projection(&self) -> P {
+        self.projection.clone()
+    }
+    fn get_depth_range(&self) -> DepthRange {
+        self.projection.get_depth_range()
+    }
+}
+
+/// A first-person camera, controlled by yaw and pitch angles.
+#[derive(Clone, Debug)]
+pub struct FirstPerson<S, P = PerspectiveFov<S, Rad<S>>> {
+    /// Eye position.
+    pub position: Point3<S>,
+    /// Rotation around the vertical axis.
+    pub yaw: Rad<S>,
+    /// Rotation around the horizontal axis, positive is looking up.
+    pub pitch: Rad<S>,
+    /// Projection.
+    pub projection: P,
+}
+
+impl<S: cgmath::BaseFloat, P> FirstPerson<S, P> {
+    /// Create a new first-person camera, looking at -Z.
+    pub fn new(position: Point3<S>, projection: P) -> FirstPerson<S, P> {
+        FirstPerson {
+            position: position,
+            yaw: Rad { s: S::zero() },
+            pitch: Rad { s: S::zero() },
+            projection: projection,
+        }
+    }
+
+    /// Turn the view. The pitch is clamped short of the vertical.
+    pub fn look(&mut self, yaw: Rad<S>, pitch: Rad<S>) {
+        self.yaw = Rad { s: self.yaw.s + yaw.s };
+        self.pitch = Rad { s: clamp_pitch(self.pitch.s + pitch.s) };
+    }
+
+    /// Move relative to the horizontal view direction, keeping the
+    /// vertical movement independent of the pitch.
+    pub fn walk(&mut self, forward: S, right: S, up: S) {
+        use cgmath::{Point, Rotation, Rotation3};
+        let rot: Quaternion<S> = Rotation3::from_angle_y(self.yaw);
+        let offset = rot.rotate_vector(&Vector3::new(right, up, -forward));
+        self.position = self.position.add_v(&offset);
+    }
+
+    /// Move relative to the full view orientation.
+    pub fn fly(&mut self, forward: S, right: S, up: S) {
+        use cgmath::{Point, Rotation};
+        let rot = yaw_pitch(self.yaw, self.pitch);
+        let offset = rot.rotate_vector(&Vector3::new(right, up, -forward));
+        self.position = self.position.add_v(&offset);
+    }
+}
+
+impl<S: cgmath::BaseFloat, P> ::Node for FirstPerson<S, P> {
+    type Transform = Transform<S>;
+    fn get_transform(&self) -> Transform<S> {
+        use cgmath::Point;
+        Decomposed {
+            scale: S::one(),
+            rot: yaw_pitch(self.yaw, self.pitch),
+            disp: self.position.to_vec(),
+        }
+    }
+}
+
+impl<S: cgmath::BaseFloat, P> ::Camera<S> for FirstPerson<S, P> where
+    P: Clone + Into<cgmath::Matrix4<S>> + ClipDepth,
+{
+    type Projection = P;
+    fn get_projection(&self) -> P {
+        self.projection.clone()
+    }
+    fn get_depth_range(&self) -> DepthRange {
+        self.projection.get_depth_range()
+    }
+}
+
+/// Clip space depth convention of a projection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthRange {
+    /// Standard GL convention: depth goes from -1 at near to 1 at far.
+    NegativeOneToOne,
+    /// Depth goes from 0 at near to 1 at far.
+    ZeroToOne,
+    /// Reversed-Z: depth goes from 1 at near to 0 at far.
+    Reversed,
+}
+
+/// A projection with a known clip space depth convention, which the
+/// contexts configure their cullers with.
+pub trait ClipDepth {
+    /// Get the depth convention of the projection matrix.
+    fn get_depth_range(&self) -> DepthRange;
+}
+
+impl<S> ClipDepth for PerspectiveFov<S, Rad<S>> {
+    fn get_depth_range(&self) -> DepthRange {
+        DepthRange::NegativeOneToOne
+    }
+}
+
+impl<S> ClipDepth for Ortho<S> {
+    fn get_depth_range(&self) -> DepthRange {
+        DepthRange::NegativeOneToOne
+    }
+}
+
+impl<S> ClipDepth for cgmath::Matrix4<S> {
+    /// Plain matrices are assumed to follow the standard GL convention.
+    fn get_depth_range(&self) -> DepthRange {
+        DepthRange::NegativeOneToOne
+    }
+}
+
+impl DepthRange {
+    /// Compute the depth of the model origin for sorting, given the
+    /// model-view-projection matrix. Smaller values are closer to the
+    /// camera under any convention, so that `gfx_phase::sort` functions
+    /// keep their meaning. Suitable for `ToDepth` implementations.
+    pub fn get_depth<S: cgmath::BaseFloat>(&self, mvp: &cgmath::Matrix4<S>) -> S {
+        let depth = mvp[3][2] / mvp[3][3];
+        match *self {
+            DepthRange::Reversed => -depth,
+            _ => depth,
+        }
+    }
+}
+
+fn perspective_matrix<S: cgmath::BaseFloat>(fovy: Rad<S>, aspect: S, a: S, b: S)
+                      -> cgmath::Matrix4<S> {
+    let f = S::one() / (fovy.s / S::from(2).unwrap()).tan();
+    let (zero, one) = (S::zero(), S::one());
+    cgmath::Matrix4::new(
+        f / aspect, zero, zero, zero,
+        zero, f, zero, zero,
+        zero, zero, a, -one,
+        zero, zero, b, zero)
+}
+
+/// Perspective projection with reversed-Z, mapping the near plane to
+/// the depth of 1 and the far plane to 0, in the `[0, 1]` clip range.
+/// Gives a better depth precision with floating-point depth buffers.
+#[derive(Clone, Debug)]
+pub struct ReversedPerspective<S> {
+    /// Vertical field of view.
+    pub fovy: Rad<S>,
+    /// Width to height ratio.
+    pub aspect: S,
+    /// Near plane distance.
+    pub near: S,
+    /// Far plane distance.
+    pub far: S,
+}
+
+impl<S> ClipDepth for ReversedPerspective<S> {
+    fn get_depth_range(&self) -> DepthRange {
+        DepthRange::Reversed
+    }
+}
+
+impl<S: cgmath::BaseFloat> From<ReversedPerspective<S>> for cgmath::Matrix4<S> {
+    fn from(p: ReversedPerspective<S>) -> cgmath::Matrix4<S> {
+        let range = p.far - p.near;
+        perspective_matrix(p.fovy, p.aspect, p.near / range, p.far * p.near / range)
+    }
+}
+
+/// Perspective projection with the far plane at infinity.
+#[derive(Clone, Debug)]
+pub struct InfinitePerspective<S> {
+    /// Vertical field of view.
+    pub fovy: Rad<S>,
+    /// Width to height ratio.
+    pub aspect: S,
+    /// Near plane distance.
+    pub near: S,
+    /// Depth convention.
+    pub depth: DepthRange,
+}
+
+impl<S> ClipDepth for InfinitePerspective<S> {
+    fn get_depth_range(&self) -> DepthRange {
+        self.depth
+    }
+}
+
+impl<S: cgmath::BaseFloat> From<InfinitePerspective<S>> for cgmath::Matrix4<S> {
+    fn from(p: InfinitePerspective<S>) -> cgmath::Matrix4<S> {
+        let (zero, one) = (S::zero(), S::one());
+        let (a, b) = match p.depth {
+            DepthRange::NegativeOneToOne => (-one, -S::from(2).unwrap() * p.near),
+            DepthRange::ZeroToOne => (-one, -p.near),
+            DepthRange::Reversed => (zero, p.near),
+        };
+        perspective_matrix(p.fovy, p.aspect, a, b)
+    }
+}
@@ -0,0 +1,237 @@
+//! Clustered light assignment. The view frustum is split into a grid of
+//! clusters, uniform in screen space and exponential in depth, and every
+//! cluster receives the list of lights touching it. The grid and the
+//! index list are meant to be uploaded into buffers, so that the shader
+//! can find the cluster of a fragment and iterate its lights.
+
+use cgmath;
+use cgmath::{Matrix4, Point3, Vector3, Vector4};
+use collision::Aabb3;
+use light::{Kind, Light};
+
+/// Cluster entry: offset into the index list and the number of lights.
+pub type Cell = [u32; 2];
+
+/// Light grid of a camera.
+pub struct Clusters<S> {
+    /// Number of clusters along screen X, screen Y, and depth.
+    pub dimensions: [usize; 3],
+    /// View space depth of the first slice start.
+    pub near: S,
+    /// View space depth of the last slice end.
+    pub far: S,
+    bounds: Vec<Aabb3<S>>,
+    pairs: Vec<(u32, u32)>,
+    grid: Vec<Cell>,
+    indices: Vec<u32>,
+}
+
+impl<S: cgmath::BaseFloat> Clusters<S> {
+    /// Create a new grid, covering the view depth range from `near` to `far`.
+    /// Panics if any of the dimensions is zero, or unless `0 < near < far`,
+    /// which the exponential depth slicing relies on.
+    pub fn new(dimensions: [usize; 3], near: S, far: S) -> Clusters<S> {
+        assert!(dimensions.iter().all(|&d| d > 0), "Empty cluster grid: {:?}", dimensions);
+        assert!(S::zero() < near && near < far, "Invalid cluster depth range");
+        Clusters {
+            dimensions: dimensions,
+            near: near,
+            far: far,
+            bounds: Vec::new(),
+            pairs: Vec::new(),
+            grid: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    /// Get the total number of clusters.
+    pub fn get_count(&self) -> usize {
+        self.dimensions[0] * self.dimensions[1] * self.dimensions[2]
+    }
+
+    /// Get the linear index of a cluster.
+    pub fn get_index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + self.dimensions[0] * (y + self.dimensions[1] * z)
+    }
+
+    /// Get the view space depth where a slice starts.
+    pub fn get_slice_depth(&self, slice: usize) -> S {
+        let t = S::from(slice).unwrap() / S::from(self.dimensions[2]).unwrap();
+        self.near * (self.far / self.near).powf(t)
+    }
+
+    /// Get the scale and bias, such that the slice of a view space
+    /// depth `d` is `floor(ln(d) * scale + bias)`, for use in shaders.
+    pub fn get_depth_scale_bias(&self) -> (S, S) {
+        let scale = S::from(self.dimensions[2]).unwrap() / (self.far / self.near).ln();
+        (scale, -self.near.ln() * scale)
+    }
+
+    /// Get the slice containing a view space depth, clamped to the range.
+    pub fn get_slice(&self, depth: S) -> usize {
+        if depth <= self.near {
+            return 0
+        }
+        let (scale, bias) = self.get_depth_scale_bias();
+        let slice = (depth.ln() * scale + bias).floor().to_usize().unwrap_or(0);
+        slice.min(self.dimensions[2] - 1)
+    }
+
+    /// Get the cluster entries, to be indexed by `get_index`.
+    pub fn get_grid(&self) -> &[Cell] {
+        &self.grid
+    }
+
+    /// Get the light indices referenced by the cluster entries.
+    pub fn get_indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// Compute the view space bounds of the clusters for a projection.
+    fn compute_bounds(&mut self, projection: &Matrix4<S>) {
+        use cgmath::{Matrix, Point, Vector};
+        let inverse = projection.invert().expect("projection is not invertible");
+        // Points on two depths inside the clip volume define the ray
+        // through a screen point, regardless of the depth convention.
+        let unproject = |x: S, y: S, z: S| {
+            let v = inverse.mul_v(&Vector4::new(x, y, z, S::one()));
+            Point3::from_vec(&v.truncate().div_s(v.w))
+        };
+        let (one, two) = (S::one(), S::one() + S::one());
+        let quarter = one / (two * two);
+        let (nx, ny, nz) = (self.dimensions[0], self.dimensions[1], self.dimensions[2]);
+        let mut rays = Vec::with_capacity((nx + 1) * (ny + 1));
+        for y in 0 .. ny + 1 {
+            for x in 0 .. nx + 1 {
+                let sx = two * S::from(x).unwrap() / S::from(nx).unwrap() - one;
+                let sy = two * S::from(y).unwrap() / S::from(ny).unwrap() - one;
+                let p0 = unproject(sx, sy, quarter);
+                let p1 = unproject(sx, sy, quarter * two);
+                rays.push((p0, p1.sub_p(&p0)));
+            }
+        }
+        let at_depth = |&(ref p0, ref dir): &(Point3<S>, Vector3<S>), depth: S| {
+            // the camera is looking at -Z
+            let t = (-depth - p0.z) / dir.z;
+            p0.add_v(&dir.mul_s(t))
+        };
+        let depths: Vec<S> = (0 .. nz + 1).map(|z| self.get_slice_depth(z)).collect();
+        self.bounds.clear();
+        for z in 0 .. nz {
+            for y in 0 .. ny {
+                for x in 0 .. nx {
+                    let corners = [
+                        &rays[y * (nx + 1) + x], &rays[y * (nx + 1) + x + 1],
+                        &rays[(y + 1) * (nx + 1) + x], &rays[(y + 1) * (nx + 1) + x + 1],
+                    ];
+                    let points = corners.iter().flat_map(|r|
+                        vec![at_depth(r, depths[z]), at_depth(r, depths[z + 1])].into_iter());
+                    self.bounds.push(::bound::aabb_from_points(points).unwrap());
+                }
+            }
+        }
+    }
+
+    /// Assign the lights to the clusters of a camera. `visible` lists the
+    /// indices of the lights to consider, e.g. as returned by
+    /// `light::cull`. Directional lights affect every cluster and are
+    /// expected to be handled separately, so they are skipped.
+    pub fn build<C>(&mut self, camera: &C, lights: &[Light<S>], visible: &[usize]) where
+        C: ::Camera<S>,
+        C::Transform: cgmath::Transform3<S>,
+    {
+        use cgmath::{EuclideanVector, Point, Transform};
+        let projection: Matrix4<S> = camera.get_projection().into();
+        self.compute_bounds(&projection);
+        let view = camera.get_transform().invert().unwrap();
+
+        self.pairs.clear();
+        for &i in visible.iter() {
+            let light = &lights[i];
+            if let Kind::Directional = light.kind {
+                continue
+            }
+            let center = view.transform_point(&light.get_position());
+            let radius = light.range * light.transform.scale;
+            let depth = -center.z;
+            if depth + radius < self.near || depth - radius > self.far {
+                continue
+            }
+            let z0 = self.get_slice(depth - radius);
+            let z1 = self.get_slice(depth + radius);
+            let per_slice = self.dimensions[0] * self.dimensions[1];
+            for cluster in z0 * per_slice .. (z1 + 1) * per_slice {
+                let b = &self.bounds[cluster];
+                let closest = Point3::new(center.x.max(b.min.x).min(b.max.x),
+                                          center.y.max(b.min.y).min(b.max.y),
+                                          center.z.max(b.min.z).min(b.max.z));
+                if closest.sub_p(&center).length2() <= radius * radius {
+                    self.pairs.push((cluster as u32, i as u32));
+                }
+            }
+        }
+        // keep the light order within each cluster
+        self.pairs.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let count = self.get_count();
+        self.grid.clear();
+        self.grid.resize(count, [0, 0]);
+        self.indices.clear();
+        for &(cluster, light) in self.pairs.iter() {
+            let cell = &mut self.grid[cluster as usize];
+            if cell[1] == 0 {
+                cell[0] = self.indices.len() as u32;
+            }
+            cell[1] += 1;
+            self.indices.push(light);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Clusters;
+
+    fn approx(a: f32, b: f32) -> bool {
+        (a - b).abs() <= 1e-4 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn slice_depths() {
+        let c = Clusters::new([16, 8, 24], 0.1f32, 1000.0);
+        assert!(approx(c.get_slice_depth(0), 0.1));
+        assert!(approx(c.get_slice_depth(24), 1000.0));
+        // exponential: the ratio between the consecutive slices is constant
+        let ratio = c.get_slice_depth(1) / c.get_slice_depth(0);
+        for i in 1..24 {
+            assert!(approx(c.get_slice_depth(i + 1) / c.get_slice_depth(i), ratio));
+        }
+    }
+
+    #[test]
+    fn slice_lookup() {
+        let c = Clusters::new([16, 8, 24], 0.1f32, 1000.0);
+        for i in 0..24 {
+            let start = c.get_slice_depth(i);
+            let end = c.get_slice_depth(i + 1);
+            assert_eq!(c.get_slice((start * end).sqrt()), i);
+        }
+        assert_eq!(c.get_slice(0.01), 0);
+        assert_eq!(c.get_slice(5000.0), 23);
+        let (scale, bias) = c.get_depth_scale_bias();
+        assert!(approx(0.1f32.ln() * scale + bias, 0.0));
+        assert!(approx(1000f32.ln() * scale + bias, 24.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn no_slices() {
+        Clusters::new([16, 8, 0], 0.1f32, 1000.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_near() {
+        Clusters::new([16, 8, 24], 0.0f32, 1000.0);
+    }
+}
@@ -4,14 +4,35 @@ use collision;
 use gfx;
 use gfx_phase;
 use hprof;
+use camera::DepthRange;
 
 
 /// Generic bound culler.
 pub trait Culler<S: cgmath::BaseFloat, B: collision::Bound<S>> {
     /// Start a new culling session.
     fn init(&mut self);
+    /// Set the clip space depth convention of the matrices to cull with.
+    /// The contexts call it with the depth range of the camera.
+    fn set_depth_range(&mut self, _depth: DepthRange) {}
     /// Cull a bound with a given transformation matrix.
     fn cull(&mut self, &B, &cgmath::Matrix4<S>) -> collision::Relation;
+    /// Cull a batch of bounds with their transformation matrices,
+    /// appending the relations to `out`.
+    fn cull_many(&mut self, bounds: &[B], mvps: &[cgmath::Matrix4<S>],
+                 out: &mut Vec<collision::Relation>) {
+        for (b, m) in bounds.iter().zip(mvps.iter()) {
+            let relation = self.cull(b, m);
+            out.push(relation);
+        }
+    }
+    /// Cull a batch of bounds like `cull_many`, given the slots of their
+    /// entities in the drawn sequence. Cullers keeping state per entity
+    /// should key it by the slot, which doesn't depend on the other
+    /// entities being skipped.
+    fn cull_slots(&mut self, _slots: &[usize], bounds: &[B], mvps: &[cgmath::Matrix4<S>],
+                  out: &mut Vec<collision::Relation>) {
+        self.cull_many(bounds, mvps, out)
+    }
 }
 
 impl<S, B: collision::Bound<S>> Culler<S, B> for ()
@@ -23,23 +44,606 @@ impl<S, B: collision::Bound<S>> Culler<S, B> for ()
     }
 }
 
-/// Frustum culler.
-pub struct Frustum<S, B>(PhantomData<(S, B)>);
+/// Frustum culler. The standard GL depth range goes through
+/// `Bound::relate_clip_space`, the other conventions and the far plane at
+/// infinity go through the same plane extraction, with the depth rows
+/// adjusted by `get_clip_planes`.
+#[derive(Clone, Debug)]
+pub struct Frustum<S, B> {
+    depth: DepthRange,
+    dummy: PhantomData<(S, B)>,
+}
 
 impl<S, B> Frustum<S, B> {
-    /// Create a new frustum culler.
+    /// Create a new frustum culler, assuming the standard GL depth range
+    /// until a context sets the one of its camera.
     pub fn new() -> Frustum<S, B> {
-        Frustum(PhantomData)
+        Frustum::with_depth(DepthRange::NegativeOneToOne)
+    }
+
+    /// Create a new frustum culler for a given clip space depth convention.
+    pub fn with_depth(depth: DepthRange) -> Frustum<S, B> {
+        Frustum {
+            depth: depth,
+            dummy: PhantomData,
+        }
+    }
+}
+
+/// Get the clip planes of a projection matrix, as vectors of plane
+/// equation coefficients. Planes at infinity come out degenerate,
+/// having a zero normal.
+pub fn get_clip_planes<S>(mx: &cgmath::Matrix4<S>, depth: DepthRange)
+                       -> [cgmath::Vector4<S>; 6] where
+    S: cgmath::BaseFloat,
+{
+    use cgmath::{Matrix, Vector};
+    let (r0, r1, r2, r3) = (mx.row(0), mx.row(1), mx.row(2), mx.row(3));
+    let near = match depth {
+        DepthRange::NegativeOneToOne => r3.add_v(&r2),
+        DepthRange::ZeroToOne => r2,
+        DepthRange::Reversed => r3.sub_v(&r2),
+    };
+    let far = match depth {
+        DepthRange::NegativeOneToOne |
+        DepthRange::ZeroToOne => r3.sub_v(&r2),
+        DepthRange::Reversed => r2,
+    };
+    [r3.add_v(&r0), r3.sub_v(&r0),
+     r3.add_v(&r1), r3.sub_v(&r1),
+     near, far]
+}
+
+/// Relate a bound to a set of clip planes, given as plane equation
+/// coefficients like in `get_clip_planes`. Degenerate planes (at infinity)
+/// are skipped.
+pub fn relate_planes<S, B>(bound: &B, planes: &[cgmath::Vector4<S>]) -> collision::Relation where
+    S: cgmath::BaseFloat,
+    B: collision::Bound<S>,
+{
+    let mut relation = collision::Relation::In;
+    for v in planes.iter() {
+        // same construction as `collision::Frustum::from_matrix4`
+        let plane = match collision::Plane::from_vector4_alt(*v).normalize() {
+            Some(p) => p,
+            None => continue,
+        };
+        match bound.relate_plane(&plane) {
+            collision::Relation::Out => return collision::Relation::Out,
+            collision::Relation::Cross => relation = collision::Relation::Cross,
+            collision::Relation::In => (),
+        }
     }
+    relation
 }
 
 impl<S: cgmath::BaseFloat, B: collision::Bound<S>> Culler<S, B> for Frustum<S, B> {
     fn init(&mut self) {}
+    fn set_depth_range(&mut self, depth: DepthRange) {
+        self.depth = depth;
+    }
     fn cull(&mut self, bound: &B, mvp: &cgmath::Matrix4<S>) -> collision::Relation {
-        bound.relate_clip_space(mvp)
+        let planes = get_clip_planes(mvp, self.depth);
+        let zero = S::zero();
+        let far = planes[5];
+        match self.depth {
+            // the far plane at infinity is not supported by `collision`
+            DepthRange::NegativeOneToOne if far.x != zero || far.y != zero || far.z != zero =>
+                bound.relate_clip_space(mvp),
+            _ => relate_planes(bound, &planes),
+        }
     }
 }
 
+/// Cached result of a single culling test.
+#[derive(Clone, Debug)]
+struct Record<S, B> {
+    bound: B,
+    mvp: cgmath::Matrix4<S>,
+    relation: collision::Relation,
+}
+
+/// Temporal culler, exploiting the visibility coherency between frames.
+/// It remembers the relation, the bound, and the model-view-projection
+/// matrix of each test, recognized by the entity slot when culled through
+/// `cull_slots` (as the drawing loop does), or by the order of the tests
+/// within a session otherwise, and only delegates to the inner culler when the bound or the
+/// matrix have changed (the entity or the camera have moved) or the bound
+/// was crossing the frustum boundary. The cached relation is only reused
+/// for the very same bound and matrix, so a changed order of tests costs
+/// extra misses but never gives wrong results. `reset` forgets the cache.
+/// It is not `Clone`, since the clones would not share the cache.
+pub struct Temporal<S, B, U> {
+    inner: U,
+    records: Vec<Option<Record<S, B>>>,
+    index: usize,
+    depth: Option<DepthRange>,
+    // scratch buffers of `cull_slots`, kept between the calls
+    missed: Vec<(usize, usize)>,
+    miss_bounds: Vec<B>,
+    miss_mvps: Vec<cgmath::Matrix4<S>>,
+    miss_relations: Vec<collision::Relation>,
+    /// Number of tests answered from the cache in the current session.
+    pub hits: usize,
+    /// Number of tests delegated to the inner culler in the current session.
+    pub misses: usize,
+}
+
+impl<S, B, U> Temporal<S, B, U> {
+    /// Create a new temporal culler on top of another one.
+    pub fn new(inner: U) -> Temporal<S, B, U> {
+        Temporal {
+            inner: inner,
+            records: Vec::new(),
+            index: 0,
+            depth: None,
+            missed: Vec::new(),
+            miss_bounds: Vec::new(),
+            miss_mvps: Vec::new(),
+            miss_relations: Vec::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Forget all the cached relations.
+    pub fn reset(&mut self) {
+        self.records.clear();
+    }
+
+    /// Unwrap the inner culler.
+    pub fn into_inner(self) -> U {
+        self.inner
+    }
+}
+
+impl<S, B, U> Culler<S, B> for Temporal<S, B, U> where
+    S: cgmath::BaseFloat,
+    B: collision::Bound<S> + Clone + PartialEq,
+    U: Culler<S, B>,
+{
+    fn init(&mut self) {
+        self.index = 0;
+        self.hits = 0;
+        self.misses = 0;
+        self.inner.init();
+    }
+
+    fn set_depth_range(&mut self, depth: DepthRange) {
+        // the cached relations only hold for the same convention
+        if self.depth != Some(depth) {
+            self.depth = Some(depth);
+            self.records.clear();
+        }
+        self.inner.set_depth_range(depth);
+    }
+
+    fn cull(&mut self, bound: &B, mvp: &cgmath::Matrix4<S>) -> collision::Relation {
+        let id = self.index;
+        self.index += 1;
+        if let Some(relation) = self.lookup(id, bound, mvp) {
+            return relation
+        }
+        let relation = self.inner.cull(bound, mvp);
+        self.store(id, bound, mvp, relation);
+        relation
+    }
+
+    fn cull_many(&mut self, bounds: &[B], mvps: &[cgmath::Matrix4<S>],
+                 out: &mut Vec<collision::Relation>) {
+        let first = self.index;
+        self.index += bounds.len();
+        self.cull_batch(first.., bounds, mvps, out)
+    }
+
+    fn cull_slots(&mut self, slots: &[usize], bounds: &[B], mvps: &[cgmath::Matrix4<S>],
+                  out: &mut Vec<collision::Relation>) {
+        self.cull_batch(slots.iter().cloned(), bounds, mvps, out)
+    }
+}
+
+impl<S, B, U> Temporal<S, B, U> where
+    S: cgmath::BaseFloat,
+    B: collision::Bound<S> + Clone + PartialEq,
+    U: Culler<S, B>,
+{
+    fn cull_batch<I>(&mut self, ids: I, bounds: &[B], mvps: &[cgmath::Matrix4<S>],
+                     out: &mut Vec<collision::Relation>) where
+        I: Iterator<Item = usize>,
+    {
+        use std::mem;
+        // answer the hits, and pass the misses to the inner culler at once
+        let mut missed = mem::replace(&mut self.missed, Vec::new());
+        let mut miss_bounds = mem::replace(&mut self.miss_bounds, Vec::new());
+        let mut miss_mvps = mem::replace(&mut self.miss_mvps, Vec::new());
+        let mut relations = mem::replace(&mut self.miss_relations, Vec::new());
+        for (id, (bound, mvp)) in ids.zip(bounds.iter().zip(mvps.iter())) {
+            match self.lookup(id, bound, mvp) {
+                Some(relation) => out.push(relation),
+                None => {
+                    missed.push((id, out.len()));
+                    miss_bounds.push(bound.clone());
+                    miss_mvps.push(*mvp);
+                    out.push(collision::Relation::Cross);
+                },
+            }
+        }
+        self.inner.cull_many(&miss_bounds, &miss_mvps, &mut relations);
+        for (k, &(id, pos)) in missed.iter().enumerate() {
+            out[pos] = relations[k];
+            self.store(id, &miss_bounds[k], &miss_mvps[k], relations[k]);
+        }
+        missed.clear();
+        miss_bounds.clear();
+        miss_mvps.clear();
+        relations.clear();
+        self.missed = missed;
+        self.miss_bounds = miss_bounds;
+        self.miss_mvps = miss_mvps;
+        self.miss_relations = relations;
+    }
+
+    fn lookup(&mut self, id: usize, bound: &B, mvp: &cgmath::Matrix4<S>)
+              -> Option<collision::Relation> {
+        match self.records.get(id) {
+            Some(&Some(ref rec)) if rec.relation != collision::Relation::Cross &&
+                rec.mvp == *mvp && rec.bound == *bound => {
+                self.hits += 1;
+                Some(rec.relation)
+            },
+            _ => {
+                self.misses += 1;
+                None
+            },
+        }
+    }
+
+    fn store(&mut self, id: usize, bound: &B, mvp: &cgmath::Matrix4<S>,
+             relation: collision::Relation) {
+        let rec = Record {
+            bound: bound.clone(),
+            mvp: *mvp,
+            relation: relation,
+        };
+        while self.records.len() <= id {
+            self.records.push(None);
+        }
+        self.records[id] = Some(rec);
+    }
+}
+
+/// Enqueue the fragments of a visible entity into a phase.
+pub fn enqueue_fragments<R, M, V, H>(phase: &mut H, mesh: &gfx::Mesh<R>,
+                         fragments: &[::Fragment<R, M>], view_info: &V,
+                         report: &mut ::Report) -> Result<(), ::Error> where
+    R: gfx::Resources,
+    V: gfx_phase::ToDepth,
+    H: gfx_phase::AbstractPhase<R, M, V>,
+{
+    for frag in fragments.iter() {
+        match phase.enqueue(mesh, &frag.slice, &frag.material, view_info) {
+            Ok(true)  => {
+                report.primitives_rendered += frag.slice.get_prim_count();
+                report.calls_passed += 1;
+            },
+            Ok(false) => report.calls_rejected += 1,
+            Err(e)    => return Err(::Error::Batch(e)),
+        }
+    }
+    Ok(())
+}
+
+/// Placement of a visible entity relative to the camera, given to hooks.
+pub struct Placement<'a, S: 'a, T: 'a> {
+    /// Index of the entity in the drawn sequence, counting the skipped ones.
+    pub slot: usize,
+    /// Model -> world transform.
+    pub model: &'a T,
+    /// Model -> camera transform.
+    pub view: &'a T,
+    /// Model-view-projection matrix.
+    pub mvp: &'a cgmath::Matrix4<S>,
+    /// Projection matrix.
+    pub projection: &'a cgmath::Matrix4<S>,
+}
+
+/// Per-entity extension of the drawing loop. For every visible entity the
+/// hook receives the view information and decides what to draw, pushing
+/// fragment lists with their own view information into `parts`.
+pub trait Hook<'b, R: gfx::Resources + 'b, M: 'b, E: ::Entity<R, M> + 'b, S, T, V> {
+    /// Count the calls of an entity skipped in a given slot, by default
+    /// all of its fragments.
+    fn count(&self, _slot: usize, entity: &'b E) -> ::Count {
+        entity.get_fragments().len() as ::Count
+    }
+    /// Select the parts of a visible entity to draw.
+    fn select(&mut self, entity: &'b E, place: &Placement<S, T>, view_info: V,
+              parts: &mut Vec<(&'b [::Fragment<R, M>], V)>, report: &mut ::Report);
+}
+
+/// Hook drawing all the fragments of an entity, with the view information
+/// adjusted by a closure.
+#[derive(Clone, Copy)]
+pub struct Fix<F>(pub F);
+
+impl<'b, R, M, E, S, T, V, F> Hook<'b, R, M, E, S, T, V> for Fix<F> where
+    R: gfx::Resources + 'b,
+    M: 'b,
+    E: ::Entity<R, M> + 'b,
+    F: FnMut(&E, &mut V),
+{
+    fn select(&mut self, entity: &'b E, _: &Placement<S, T>, mut view_info: V,
+              parts: &mut Vec<(&'b [::Fragment<R, M>], V)>, _: &mut ::Report) {
+        (self.0)(entity, &mut view_info);
+        parts.push((entity.get_fragments(), view_info));
+    }
+}
+
+/// Entity filter of a drawing pass.
+#[derive(Clone, Copy, Debug)]
+pub struct Filter {
+    /// Layers of the pass.
+    pub layers: ::Layers,
+    /// Only accept the shadow casters.
+    pub casters_only: bool,
+}
+
+impl Filter {
+    /// Create a filter accepting everything.
+    pub fn new() -> Filter {
+        Filter {
+            layers: ::LAYER_ALL,
+            casters_only: false,
+        }
+    }
+
+    /// Check if anything of given layers and shadow casting is excluded.
+    pub fn excludes(&self, layers: ::Layers, caster: bool) -> bool {
+        layers & self.layers == 0 || (self.casters_only && !caster)
+    }
+
+    /// Check if an entity is excluded.
+    pub fn rejects<R, M, E>(&self, entity: &E) -> bool where
+        R: gfx::Resources,
+        E: ::Entity<R, M>,
+    {
+        self.excludes(entity.get_layers(), entity.is_shadow_caster())
+    }
+}
+
+/// Reason of skipping an entity in the drawing loop.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Skip {
+    Invisible,
+    Masked,
+    Culled,
+}
+
+/// Destination of the drawing loop: counts the calls of the skipped
+/// entities and enqueues the fragments of the visible ones.
+trait Sink<R: gfx::Resources, M, V> {
+    /// Check if anything of given layers is wanted at all.
+    fn accepts(&self, _layers: ::Layers) -> bool {
+        true
+    }
+    /// Count the calls of a skipped entity with given layers.
+    fn skip(&mut self, layers: ::Layers, calls: ::Count, reason: Skip);
+    /// Enqueue the fragments of a visible entity with given layers.
+    fn enqueue(&mut self, layers: ::Layers, mesh: &gfx::Mesh<R>,
+               fragments: &[::Fragment<R, M>], view_info: &V)
+               -> Result<(), ::Error>;
+    /// Get the report the hooks count into.
+    fn get_report(&mut self) -> &mut ::Report;
+}
+
+fn count_skip(report: &mut ::Report, calls: ::Count, reason: Skip) {
+    match reason {
+        Skip::Invisible => report.calls_invisible += calls,
+        Skip::Masked => report.calls_masked += calls,
+        Skip::Culled => report.calls_culled += calls,
+    }
+}
+
+/// Sink of a single phase.
+struct PhaseSink<'a, H: 'a> {
+    phase: &'a mut H,
+    report: &'a mut ::Report,
+}
+
+impl<'a, R, M, V, H> Sink<R, M, V> for PhaseSink<'a, H> where
+    R: gfx::Resources,
+    V: gfx_phase::ToDepth,
+    H: gfx_phase::AbstractPhase<R, M, V>,
+{
+    fn skip(&mut self, _: ::Layers, calls: ::Count, reason: Skip) {
+        count_skip(&mut *self.report, calls, reason)
+    }
+
+    fn enqueue(&mut self, _: ::Layers, mesh: &gfx::Mesh<R>,
+               fragments: &[::Fragment<R, M>], view_info: &V)
+               -> Result<(), ::Error> {
+        enqueue_fragments(&mut *self.phase, mesh, fragments, view_info, &mut *self.report)
+    }
+
+    fn get_report(&mut self) -> &mut ::Report {
+        &mut *self.report
+    }
+}
+
+/// Sink of a group of phases, each with its own layers and report.
+/// The calls of a skipped entity count as masked for the phases that
+/// exclude its layers, whatever the reason of skipping is.
+struct GroupSink<'a, G: ?Sized + 'a> {
+    phases: &'a mut G,
+    masks: Vec<::Layers>,
+    reports: Vec<::Report>,
+    /// Counted by the hooks, shared by all the phases.
+    shared: ::Report,
+}
+
+impl<'a, R, M, V, G> Sink<R, M, V> for GroupSink<'a, G> where
+    R: gfx::Resources,
+    V: gfx_phase::ToDepth,
+    G: ::PhaseGroup<R, M, V> + ?Sized,
+{
+    fn accepts(&self, layers: ::Layers) -> bool {
+        self.masks.iter().any(|&m| layers & m != 0)
+    }
+
+    fn skip(&mut self, layers: ::Layers, calls: ::Count, reason: Skip) {
+        for (rep, &mask) in self.reports.iter_mut().zip(self.masks.iter()) {
+            let reason = match reason {
+                Skip::Culled if layers & mask == 0 => Skip::Masked,
+                other => other,
+            };
+            count_skip(rep, calls, reason);
+        }
+    }
+
+    fn enqueue(&mut self, layers: ::Layers, mesh: &gfx::Mesh<R>,
+               fragments: &[::Fragment<R, M>], view_info: &V)
+               -> Result<(), ::Error> {
+        for frag in fragments.iter() {
+            for (i, rep) in self.reports.iter_mut().enumerate() {
+                if layers & self.masks[i] == 0 {
+                    rep.calls_masked += 1;
+                    continue
+                }
+                match self.phases.enqueue(i, mesh, &frag.slice, &frag.material, view_info) {
+                    Ok(true)  => {
+                        rep.primitives_rendered += frag.slice.get_prim_count();
+                        rep.calls_passed += 1;
+                    },
+                    Ok(false) => rep.calls_rejected += 1,
+                    Err(e)    => return Err(::Error::Batch(e)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn get_report(&mut self) -> &mut ::Report {
+        &mut self.shared
+    }
+}
+
+/// Number of bounds the drawing loop passes to `Culler::cull_slots` at once.
+const CULL_BATCH: usize = 64;
+
+/// The drawing loop, shared by the contexts: filter the entities, cull
+/// them in batches through `Culler::cull_slots`, let the hook select the
+/// parts of the visible ones, and enqueue them.
+/// `locate` computes the model transform and the model-view-projection
+/// matrix of an entity, `get_view` the model-view transform.
+pub fn enqueue_entities<'b, R, M, E, I, S, T, V, U, K, H, P, Q>(
+                        entities: I, culler: &mut U, filter: Filter,
+                        projection: &cgmath::Matrix4<S>, locate: P, get_view: Q,
+                        hook: &mut K, phase: &mut H, report: &mut ::Report)
+                        -> Result<(), ::Error> where
+    R: gfx::Resources + 'b,
+    M: 'b,
+    E: ::Entity<R, M> + 'b,
+    E::Bound: collision::Bound<S>,
+    I: Iterator<Item = &'b E>,
+    S: cgmath::BaseFloat,
+    T: Clone,
+    V: ::ViewInfo<S, T>,
+    U: Culler<S, E::Bound>,
+    K: Hook<'b, R, M, E, S, T, V>,
+    H: gfx_phase::AbstractPhase<R, M, V>,
+    P: FnMut(&E) -> (T, cgmath::Matrix4<S>),
+    Q: FnMut(&T) -> T,
+{
+    let mut sink = PhaseSink {
+        phase: phase,
+        report: report,
+    };
+    enqueue_into(entities.enumerate(), culler, filter, projection,
+                 locate, get_view, hook, &mut sink)
+}
+
+/// The drawing loop over entities with their slots, into a sink.
+fn enqueue_into<'b, R, M, E, I, S, T, V, U, K, D, P, Q>(
+                entities: I, culler: &mut U, filter: Filter,
+                projection: &cgmath::Matrix4<S>, mut locate: P, mut get_view: Q,
+                hook: &mut K, sink: &mut D)
+                -> Result<(), ::Error> where
+    R: gfx::Resources + 'b,
+    M: 'b,
+    E: ::Entity<R, M> + 'b,
+    E::Bound: collision::Bound<S>,
+    I: Iterator<Item = (usize, &'b E)>,
+    S: cgmath::BaseFloat,
+    T: Clone,
+    V: ::ViewInfo<S, T>,
+    U: Culler<S, E::Bound>,
+    K: Hook<'b, R, M, E, S, T, V>,
+    D: Sink<R, M, V>,
+    P: FnMut(&E) -> (T, cgmath::Matrix4<S>),
+    Q: FnMut(&T) -> T,
+{
+    let mut parts = Vec::new();
+    let mut batch = Vec::with_capacity(CULL_BATCH);
+    let mut slots = Vec::with_capacity(CULL_BATCH);
+    let mut bounds = Vec::with_capacity(CULL_BATCH);
+    let mut mvps = Vec::with_capacity(CULL_BATCH);
+    let mut relations = Vec::with_capacity(CULL_BATCH);
+    let mut entities = entities;
+    loop {
+        // gather a batch of candidates
+        for (slot, ent) in entities.by_ref() {
+            let layers = ent.get_layers();
+            if !ent.is_visible() {
+                sink.skip(layers, hook.count(slot, ent), Skip::Invisible);
+                continue
+            }
+            if filter.rejects(ent) || !sink.accepts(layers) {
+                sink.skip(layers, hook.count(slot, ent), Skip::Masked);
+                continue
+            }
+            let (model, mvp) = locate(ent);
+            slots.push(slot);
+            bounds.push(ent.get_bound());
+            mvps.push(mvp);
+            batch.push((slot, ent, model));
+            if batch.len() == CULL_BATCH {
+                break
+            }
+        }
+        if batch.is_empty() {
+            break
+        }
+        culler.cull_slots(&slots, &bounds, &mvps, &mut relations);
+        for ((slot, ent, model), (mvp, &relation)) in batch.drain(..)
+                .zip(mvps.iter().zip(relations.iter())) {
+            let layers = ent.get_layers();
+            if relation == collision::Relation::Out {
+                sink.skip(layers, hook.count(slot, ent), Skip::Culled);
+                continue
+            }
+            let view = get_view(&model);
+            let view_info = ::ViewInfo::new(*mvp, view.clone(), model.clone());
+            {
+                let place = Placement {
+                    slot: slot,
+                    model: &model,
+                    view: &view,
+                    mvp: mvp,
+                    projection: projection,
+                };
+                hook.select(ent, &place, view_info, &mut parts, sink.get_report());
+            }
+            for (fragments, info) in parts.drain(..) {
+                try!(sink.enqueue(layers, ent.get_mesh(), fragments, &info));
+            }
+        }
+        slots.clear();
+        bounds.clear();
+        mvps.clear();
+        relations.clear();
+    }
+    Ok(())
+}
 
 /// Culler context.
 pub struct Context<'u, S, B, T, U> where
@@ -49,7 +653,9 @@ pub struct Context<'u, S, B, T, U> where
 {
     culler: &'u mut U,
     cam_inverse: T,
+    projection: cgmath::Matrix4<S>,
     view_projection: cgmath::Matrix4<S>,
+    filter: Filter,
     dummy: PhantomData<B>,
 }
 
@@ -67,25 +673,88 @@ impl<'u,
         let cam_inverse = camera.get_transform().invert().unwrap();
         let mx_proj: cgmath::Matrix4<S> = camera.get_projection().into();
         let mx_view_proj = mx_proj.mul_m(&cam_inverse.clone().into());
+        culler.set_depth_range(camera.get_depth_range());
         culler.init();
         Context {
             culler: culler,
             cam_inverse: cam_inverse,
+            projection: mx_proj,
             view_projection: mx_view_proj,
+            filter: Filter::new(),
             dummy: PhantomData
         }
     }
 
-    /// Check entity visibility.
-    pub fn is_visible<N, V>(&mut self, node: &N, bound: &B)
-                      -> Option<V> where
+    /// Set the layers of the pass. Entities outside of them are skipped.
+    pub fn set_layers(&mut self, layers: ::Layers) {
+        self.filter.layers = layers;
+    }
+
+    /// Get the layers of the pass.
+    pub fn get_layers(&self) -> ::Layers {
+        self.filter.layers
+    }
+
+    /// Only draw the shadow casters, e.g. for a shadow map pass.
+    pub fn set_casters_only(&mut self, casters_only: bool) {
+        self.filter.casters_only = casters_only;
+    }
+
+    /// Check if an entity is excluded from the pass, either by the layers
+    /// or by not casting shadows into a shadow pass.
+    pub fn is_masked<R, M, E>(&self, entity: &E) -> bool where
+        R: gfx::Resources,
+        E: ::Entity<R, M>,
+    {
+        self.filter.rejects(entity)
+    }
+
+    /// Check if anything of given layers and shadow casting, not
+    /// necessarily an entity, is excluded from the pass.
+    pub fn is_excluded(&self, layers: ::Layers, caster: bool) -> bool {
+        self.filter.excludes(layers, caster)
+    }
+
+    /// Get the camera projection matrix.
+    pub fn get_projection(&self) -> &cgmath::Matrix4<S> {
+        &self.projection
+    }
+
+    /// Get the camera view-projection matrix.
+    pub fn get_view_projection(&self) -> &cgmath::Matrix4<S> {
+        &self.view_projection
+    }
+
+    /// Get the world -> camera transform.
+    pub fn get_camera_inverse(&self) -> &T {
+        &self.cam_inverse
+    }
+
+    /// Compute the model, model-view, and model-view-projection
+    /// transforms of a node.
+    pub fn get_transforms<N>(&self, node: &N) -> (T, T, cgmath::Matrix4<S>) where
         N: ::Node<Transform = T>,
-        V: ::ViewInfo<S, T>
     {
         use cgmath::{Matrix, Transform};
         let model = node.get_transform();
         let view = self.cam_inverse.concat(&model);
         let mvp = self.view_projection.mul_m(&model.clone().into());
+        (model, view, mvp)
+    }
+
+    /// Cull a bound with a given model-view-projection matrix.
+    pub fn cull_mvp(&mut self, bound: &B, mvp: &cgmath::Matrix4<S>)
+                    -> collision::Relation {
+        self.culler.cull(bound, mvp)
+    }
+
+    /// Check entity visibility.
+    pub fn is_visible<N, V>(&mut self, node: &N, bound: &B)
+                      -> Option<V> where
+        N: ::Node<Transform = T>,
+        V: ::ViewInfo<S, T>
+    {
+        let (model, view, mvp) = self.get_transforms(node);
         if self.culler.cull(bound, &mvp) != collision::Relation::Out {
             Some(::ViewInfo::new(mvp, view, model))
         }else {
@@ -93,6 +762,104 @@ impl<'u,
         }
     }
 
+    /// Cull a node bound against the camera frustum.
+    pub fn cull<N>(&mut self, node: &N, bound: &B) -> collision::Relation where
+        N: ::Node<Transform = T>,
+    {
+        use cgmath::Matrix;
+        let mvp = self.view_projection.mul_m(&node.get_transform().into());
+        self.culler.cull(bound, &mvp)
+    }
+
+    /// Cull the entities without drawing, returning the visible ones.
+    pub fn query<'b, R, M, E, I>(&mut self, entities: I) -> Vec<&'b E> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: ::Entity<R, M, Bound = B, Transform = T> + 'b,
+        I: Iterator<Item = &'b E>,
+    {
+        entities.filter(|ent| {
+            ent.is_visible() &&
+            self.cull(*ent, &ent.get_bound()) != collision::Relation::Out
+        }).collect()
+    }
+
+    /// Run the drawing loop over the entities into a sink.
+    fn enqueue_into<'b, R, M, E, I, V, K, D>(&mut self, entities: I, hook: &mut K,
+                    sink: &mut D) -> Result<(), ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: ::Entity<R, M, Bound = B, Transform = T> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<S, T>,
+        K: Hook<'b, R, M, E, S, T, V>,
+        D: Sink<R, M, V>,
+    {
+        use cgmath::{Matrix, Transform};
+        let Context { ref mut culler, ref cam_inverse, ref projection,
+                      ref view_projection, filter, .. } = *self;
+        enqueue_into(entities.enumerate(), &mut **culler, filter, projection,
+            |ent: &E| {
+                let model = ent.get_transform();
+                let mvp = view_projection.mul_m(&model.clone().into());
+                (model, mvp)
+            },
+            |model: &T| cam_inverse.concat(model),
+            hook, sink)
+    }
+
+    /// Cull and draw the entities into a stream, letting a hook decide
+    /// what to draw for each visible entity.
+    pub fn draw_hooked<'b, R, M, E, I, V, H, X, K>(&mut self,
+                       entities: I, hook: &mut K, phase: &mut H, stream: &mut X)
+                       -> Result<::Report, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: ::Entity<R, M, Bound = B, Transform = T> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<S, T>,
+        H: gfx_phase::AbstractPhase<R, M, V>,
+        X: gfx::Stream<R>,
+        K: Hook<'b, R, M, E, S, T, V>,
+    {
+        let mut report = ::Report::new();
+
+        let g = hprof::enter("enqueue");
+        {
+            let mut sink = PhaseSink {
+                phase: phase,
+                report: &mut report,
+            };
+            try!(self.enqueue_into(entities, hook, &mut sink));
+        }
+        drop(g);
+
+        let _g = hprof::enter("flush");
+        // flush into the renderer
+        match phase.flush(stream) {
+            Ok(()) => Ok(report),
+            Err(e) => Err(::Error::Flush(e)),
+        }
+    }
+
+    /// Cull and draw the entities into a stream, adjusting the view
+    /// information of each visible entity with a closure, e.g. to pass
+    /// per-entity data to `Technique::fix_params`.
+    pub fn draw_with<'b, R, M, E, I, V, H, X, F>(&mut self,
+                     entities: I, fix: F, phase: &mut H, stream: &mut X)
+                     -> Result<::Report, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: ::Entity<R, M, Bound = B, Transform = T> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<S, T>,
+        H: gfx_phase::AbstractPhase<R, M, V>,
+        X: gfx::Stream<R>,
+        F: FnMut(&E, &mut V),
+    {
+        self.draw_hooked(entities, &mut Fix(fix), phase, stream)
+    }
+
     /// Cull and draw the entities into a stream.
     pub fn draw<'b, R, M, E, I, V, H, X>(&mut self,
                 entities: I, phase: &mut H, stream: &mut X)
@@ -105,31 +872,189 @@ impl<'u,
         H: gfx_phase::AbstractPhase<R, M, V>,
         X: gfx::Stream<R>,
     {
-        let mut report = ::Report::new();
-        // attach the profiler to the phase
+        self.draw_with(entities, |_: &E, _: &mut V| {}, phase, stream)
+    }
 
+    /// Cull the entities once and draw them into a stream through a group
+    /// of phases. Each visible fragment is offered to every phase, then the
+    /// phases are flushed in order. Returns a report per phase, where the
+    /// invisible and culled calls are counted for each of them.
+    pub fn draw_multi<'b, R, M, E, I, V, G, X>(&mut self,
+                      entities: I, phases: &mut G, stream: &mut X)
+                      -> Result<Vec<::Report>, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: ::Entity<R, M, Bound = B, Transform = T> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<S, T>,
+        G: ::PhaseGroup<R, M, V> + ?Sized,
+        X: gfx::Stream<R>,
+    {
+        self.draw_multi_masked(entities, phases, &[], stream)
+    }
+
+    /// Like `draw_multi`, but with the layers of each phase, further
+    /// restricted by the context layers. Phases past the end of `masks`
+    /// accept all the layers. The calls of an entity outside of the layers
+    /// of a phase count as masked for it, even if the entity is culled.
+    pub fn draw_multi_masked<'b, R, M, E, I, V, G, X>(&mut self,
+                             entities: I, phases: &mut G, masks: &[::Layers],
+                             stream: &mut X)
+                             -> Result<Vec<::Report>, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: ::Entity<R, M, Bound = B, Transform = T> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<S, T>,
+        G: ::PhaseGroup<R, M, V> + ?Sized,
+        X: gfx::Stream<R>,
+    {
+        let num = phases.get_count();
+        let masks: Vec<::Layers> = (0..num).map(|i|
+            self.filter.layers & masks.get(i).cloned().unwrap_or(::LAYER_ALL)
+        ).collect();
+        let mut sink = GroupSink {
+            phases: phases,
+            masks: masks,
+            reports: (0..num).map(|_| ::Report::new()).collect(),
+            shared: ::Report::new(),
+        };
 
         let g = hprof::enter("enqueue");
-        // enqueue entities fragments
-        for ent in entities {
-            let frag_count = ent.get_fragments().len() as ::Count;
-            if !ent.is_visible() {
-                report.calls_invisible += frag_count;
-                continue
+        try!(self.enqueue_into(entities, &mut Fix(|_: &E, _: &mut V| {}), &mut sink));
+        drop(g);
+
+        let GroupSink { phases, mut reports, shared, .. } = sink;
+        for rep in reports.iter_mut() {
+            rep.merge(&shared);
+        }
+
+        let _g = hprof::enter("flush");
+        for i in 0..num {
+            if let Err(e) = phases.flush(i, stream) {
+                return Err(::Error::Flush(e))
             }
-            if let Some(view_info) = self.is_visible(ent, &ent.get_bound()) {
-                for frag in ent.get_fragments().iter() {
-                    match phase.enqueue(ent.get_mesh(), &frag.slice, &frag.material, &view_info) {
-                        Ok(true)  => {
-                            report.primitives_rendered += frag.slice.get_prim_count();
-                            report.calls_passed += 1;
-                        },
-                        Ok(false) => report.calls_rejected += 1,
-                        Err(e)    => return Err(::Error::Batch(e)),
-                    }
+        }
+        Ok(reports)
+    }
+}
+
+/// Sink preparing the fragments for a shared phase, to be merged later.
+#[cfg(feature = "parallel")]
+struct PrepareSink<'a, H: 'a, P> {
+    phase: &'a H,
+    prepared: Vec<(P, ::Count)>,
+    report: ::Report,
+}
+
+#[cfg(feature = "parallel")]
+impl<'a, R, M, V, H> Sink<R, M, V> for PrepareSink<'a, H, H::Prepared> where
+    R: gfx::Resources,
+    V: gfx_phase::ToDepth,
+    H: gfx_phase::SharedPhase<R, M, V>,
+{
+    fn skip(&mut self, _: ::Layers, calls: ::Count, reason: Skip) {
+        count_skip(&mut self.report, calls, reason)
+    }
+
+    fn enqueue(&mut self, _: ::Layers, mesh: &gfx::Mesh<R>,
+               fragments: &[::Fragment<R, M>], view_info: &V)
+               -> Result<(), ::Error> {
+        for frag in fragments.iter() {
+            match self.phase.prepare(mesh, &frag.slice, &frag.material, view_info) {
+                Some(p) => self.prepared.push((p, frag.slice.get_prim_count())),
+                None => self.report.calls_rejected += 1,
+            }
+        }
+        Ok(())
+    }
+
+    fn get_report(&mut self) -> &mut ::Report {
+        &mut self.report
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn keep_view_info<E, V>(_: &E, _: &mut V) {}
+
+#[cfg(feature = "parallel")]
+impl<'u,
+    S: cgmath::BaseFloat + Sync,
+    B: collision::Bound<S>,
+    T: cgmath::Transform3<S> + Clone + Sync,
+    U: Culler<S, B> + Clone + Send + Sync,
+> Context<'u, S, B, T, U> {
+    /// Cull and draw the entities into a stream, spreading the work over
+    /// the `rayon` thread pool. Entities are split into chunks, each going
+    /// through the drawing loop with a separate clone of the culler and of
+    /// the hook, and prepared into the objects of a shared phase. The
+    /// objects are merged in the original entity order, so the result
+    /// doesn't depend on the number of threads. A zero `chunk_size` is
+    /// treated as one. The culler has to be `Clone`, which rules out
+    /// cullers caching the results between frames, like `Temporal`.
+    pub fn draw_parallel_hooked<'b, R, M, E, V, H, X, K>(&mut self,
+                                entities: &'b [E], chunk_size: usize, hook: &K,
+                                phase: &mut H, stream: &mut X)
+                                -> Result<::Report, ::Error> where
+        R: gfx::Resources + 'b,
+        M: Sync + 'b,
+        E: ::Entity<R, M, Bound = B, Transform = T> + Sync + 'b,
+        V: ::ViewInfo<S, T>,
+        H: gfx_phase::SharedPhase<R, M, V> + Sync,
+        H::Prepared: Send,
+        X: gfx::Stream<R>,
+        K: Hook<'b, R, M, E, S, T, V> + Clone + Send + Sync,
+    {
+        use cgmath::{Matrix, Transform};
+        use rayon::prelude::*;
+
+        let chunk_size = chunk_size.max(1);
+        let g = hprof::enter("enqueue");
+        let chunks: Vec<Result<(::Report, Vec<(H::Prepared, ::Count)>), ::Error>> = {
+            let culler = &*self.culler;
+            let cam_inverse = &self.cam_inverse;
+            let projection = &self.projection;
+            let view_projection = &self.view_projection;
+            let filter = self.filter;
+            let shared: &H = phase;
+            entities.par_chunks(chunk_size).enumerate().map(|(index, chunk)| {
+                let mut culler = culler.clone();
+                let mut hook = hook.clone();
+                culler.init();
+                let mut sink = PrepareSink {
+                    phase: shared,
+                    prepared: Vec::new(),
+                    report: ::Report::new(),
+                };
+                let base = index * chunk_size;
+                try!(enqueue_into(chunk.iter().enumerate().map(|(i, e)| (base + i, e)),
+                    &mut culler, filter, projection,
+                    |ent: &E| {
+                        let model = ent.get_transform();
+                        let mvp = view_projection.mul_m(&model.clone().into());
+                        (model, mvp)
+                    },
+                    |model: &T| cam_inverse.concat(model),
+                    &mut hook, &mut sink));
+                Ok((sink.report, sink.prepared))
+            }).collect()
+        };
+        drop(g);
+
+        let g = hprof::enter("merge");
+        let mut report = ::Report::new();
+        for chunk in chunks.into_iter() {
+            let (sub_report, prepared) = try!(chunk);
+            report.merge(&sub_report);
+            for (p, primitives) in prepared.into_iter() {
+                match phase.merge(p) {
+                    Ok(true)  => {
+                        report.primitives_rendered += primitives;
+                        report.calls_passed += 1;
+                    },
+                    Ok(false) => report.calls_rejected += 1,
+                    Err(e)    => return Err(::Error::Batch(e)),
                 }
-            } else {
-                report.calls_culled += frag_count;
             }
         }
         drop(g);
@@ -141,4 +1066,111 @@ impl<'u,
             Err(e) => Err(::Error::Flush(e)),
         }
     }
+
+    /// Cull and draw the entities into a stream in parallel, like
+    /// `draw_parallel_hooked` with all the fragments of the visible
+    /// entities.
+    pub fn draw_parallel<R, M, E, V, H, X>(&mut self,
+                         entities: &[E], chunk_size: usize,
+                         phase: &mut H, stream: &mut X)
+                         -> Result<::Report, ::Error> where
+        R: gfx::Resources,
+        M: Sync,
+        E: ::Entity<R, M, Bound = B, Transform = T> + Sync,
+        V: ::ViewInfo<S, T>,
+        H: gfx_phase::SharedPhase<R, M, V> + Sync,
+        H::Prepared: Send,
+        X: gfx::Stream<R>,
+    {
+        let hook = Fix(keep_view_info::<E, V> as fn(&E, &mut V));
+        self.draw_parallel_hooked(entities, chunk_size, &hook, phase, stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Matrix4, PerspectiveFov, Point3, Rad, Vector4};
+    use collision::{Aabb3, Relation};
+    use camera::{DepthRange, InfinitePerspective, ReversedPerspective};
+    use super::{Culler, Frustum, get_clip_planes};
+
+    const RANGES: [DepthRange; 3] = [DepthRange::NegativeOneToOne,
+                                     DepthRange::ZeroToOne,
+                                     DepthRange::Reversed];
+
+    fn fovy() -> Rad<f32> {
+        Rad { s: 1.0 }
+    }
+
+    fn infinite(depth: DepthRange) -> Matrix4<f32> {
+        InfinitePerspective { fovy: fovy(), aspect: 1.0, near: 1.0, depth: depth }.into()
+    }
+
+    fn finite(depth: DepthRange) -> Option<Matrix4<f32>> {
+        match depth {
+            DepthRange::NegativeOneToOne => Some(PerspectiveFov {
+                fovy: fovy(), aspect: 1.0, near: 1.0, far: 50.0,
+            }.into()),
+            DepthRange::Reversed => Some(ReversedPerspective {
+                fovy: fovy(), aspect: 1.0, near: 1.0, far: 50.0,
+            }.into()),
+            DepthRange::ZeroToOne => None,
+        }
+    }
+
+    fn distance(plane: &Vector4<f32>, z: f32) -> f32 {
+        plane.z * z + plane.w
+    }
+
+    #[test]
+    fn clip_planes_depth() {
+        for &depth in RANGES.iter() {
+            let planes = get_clip_planes(&infinite(depth), depth);
+            // the far plane is at infinity
+            let far = planes[5];
+            assert_eq!((far.x, far.y, far.z), (0.0, 0.0, 0.0), "{:?}", depth);
+            assert!(distance(&planes[4], -2.0) > 0.0, "{:?}", depth);
+            assert!(distance(&planes[4], -0.5) < 0.0, "{:?}", depth);
+            if let Some(mx) = finite(depth) {
+                let planes = get_clip_planes(&mx, depth);
+                assert!(distance(&planes[4], -2.0) > 0.0, "{:?}", depth);
+                assert!(distance(&planes[4], -0.5) < 0.0, "{:?}", depth);
+                assert!(distance(&planes[5], -40.0) > 0.0, "{:?}", depth);
+                assert!(distance(&planes[5], -60.0) < 0.0, "{:?}", depth);
+            }
+        }
+    }
+
+    #[test]
+    fn sorting_depth() {
+        for &depth in RANGES.iter() {
+            let proj = infinite(depth);
+            let at = |z: f32| {
+                let mut mx = proj;
+                mx.w = Vector4::new(proj.z.x * z, proj.z.y * z,
+                                    proj.z.z * z + proj.w.z, proj.z.w * z + proj.w.w);
+                depth.get_depth(&mx)
+            };
+            assert!(at(-2.0) < at(-10.0), "{:?}", depth);
+            assert!(at(-10.0) < at(-1000.0), "{:?}", depth);
+        }
+    }
+
+    #[test]
+    fn frustum_depth() {
+        let boxed = |z: f32| Aabb3::new(Point3::new(-0.1, -0.1, z - 0.1),
+                                        Point3::new(0.1, 0.1, z + 0.1));
+        for &depth in RANGES.iter() {
+            let mut frustum = Frustum::new();
+            frustum.set_depth_range(depth);
+            let proj = infinite(depth);
+            assert_eq!(frustum.cull(&boxed(-5.0), &proj), Relation::In, "{:?}", depth);
+            assert_eq!(frustum.cull(&boxed(-1.0), &proj), Relation::Cross, "{:?}", depth);
+            assert_eq!(frustum.cull(&boxed(3.0), &proj), Relation::Out, "{:?}", depth);
+            assert_eq!(frustum.cull(&boxed(-1.0e6), &proj), Relation::In, "{:?}", depth);
+            if let Some(mx) = finite(depth) {
+                assert_eq!(frustum.cull(&boxed(-100.0), &mx), Relation::Out, "{:?}", depth);
+            }
+        }
+    }
 }
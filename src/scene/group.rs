@@ -0,0 +1,74 @@
+//! Groups of phases, sharing a single culling pass.
+
+use gfx;
+use gfx_phase::{AbstractPhase, FlushError, ToDepth};
+
+/// A group of phases receiving the same visible fragments. Implemented
+/// for slices and tuples of phases, which are flushed in index order.
+pub trait PhaseGroup<R: gfx::Resources, M, V: ToDepth> {
+    /// Get the number of phases in the group.
+    fn get_count(&self) -> usize;
+    /// Add an entity to the queue of the phase with a given index.
+    fn enqueue(&mut self, usize, &gfx::Mesh<R>, &gfx::Slice<R>, &M, &V)
+               -> Result<bool, gfx::batch::Error>;
+    /// Flush the queue of the phase with a given index into a stream.
+    fn flush<S: gfx::Stream<R>>(&mut self, usize, &mut S)
+             -> Result<(), FlushError>;
+}
+
+impl<R, M, V, H> PhaseGroup<R, M, V> for [H] where
+    R: gfx::Resources,
+    V: ToDepth,
+    H: AbstractPhase<R, M, V>,
+{
+    fn get_count(&self) -> usize {
+        self.len()
+    }
+
+    fn enqueue(&mut self, index: usize, mesh: &gfx::Mesh<R>, slice: &gfx::Slice<R>,
+               material: &M, view_info: &V) -> Result<bool, gfx::batch::Error> {
+        self[index].enqueue(mesh, slice, material, view_info)
+    }
+
+    fn flush<S: gfx::Stream<R>>(&mut self, index: usize, stream: &mut S)
+             -> Result<(), FlushError> {
+        self[index].flush(stream)
+    }
+}
+
+macro_rules! impl_group {
+    ($count:expr, $($name:ident : $index:tt),+) => {
+        impl<R, M, V, $($name),+> PhaseGroup<R, M, V> for ($($name,)+) where
+            R: gfx::Resources,
+            V: ToDepth,
+            $($name: AbstractPhase<R, M, V>),+
+        {
+            fn get_count(&self) -> usize {
+                $count
+            }
+
+            fn enqueue(&mut self, index: usize, mesh: &gfx::Mesh<R>, slice: &gfx::Slice<R>,
+                       material: &M, view_info: &V) -> Result<bool, gfx::batch::Error> {
+                match index {
+                    $($index => self.$index.enqueue(mesh, slice, material, view_info),)+
+                    _ => panic!("Phase index {} is out of range", index),
+                }
+            }
+
+            fn flush<S: gfx::Stream<R>>(&mut self, index: usize, stream: &mut S)
+                     -> Result<(), FlushError> {
+                match index {
+                    $($index => self.$index.flush(stream),)+
+                    _ => panic!("Phase index {} is out of range", index),
+                }
+            }
+        }
+    }
+}
+
+impl_group!(1, A: 0);
+impl_group!(2, A: 0, B: 1);
+impl_group!(3, A: 0, B: 1, C: 2);
+impl_group!(4, A: 0, B: 1, C: 2, D: 3);
+impl_group!(5, A: 0, B: 1, C: 2, D: 3, E: 4);
+impl_group!(6, A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
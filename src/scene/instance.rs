@@ -0,0 +1,205 @@
+//! Instanced entity groups.
+
+use cgmath;
+use collision;
+use gfx;
+use hprof;
+use cull::{Culler, Context, enqueue_fragments};
+
+/// Per-instance data: a column-major model matrix.
+pub type Instance = [[f32; 4]; 4];
+
+/// Prefix of the instance attribute names. The model matrix columns are
+/// exposed to shaders as `a_Transform0` to `a_Transform3`.
+pub const ATTRIBUTE_PREFIX: &'static str = "a_Transform";
+
+/// View information able to carry the number of instances, to be used
+/// by `Technique::fix_instances`.
+pub trait InstanceInfo {
+    /// Set the number of visible instances.
+    fn set_instances(&mut self, gfx::InstanceCount);
+}
+
+fn get_attributes<R: gfx::Resources>(buffer: &gfx::handle::Buffer<R, Instance>)
+                  -> Vec<gfx::Attribute<R>> {
+    use gfx::attrib::{Format, FloatSize, FloatSubType, Type};
+    let stride = ::std::mem::size_of::<Instance>();
+    (0..4).map(|i| gfx::Attribute {
+        name: format!("{}{}", ATTRIBUTE_PREFIX, i),
+        buffer: buffer.raw().clone(),
+        format: Format {
+            elem_count: 4,
+            elem_type: Type::Float(FloatSubType::Default, FloatSize::F32),
+            offset: (i * stride / 4) as gfx::attrib::Offset,
+            stride: stride as gfx::attrib::Stride,
+            instance_rate: 1,
+        },
+    }).collect()
+}
+
+/// A group of identical objects, sharing the mesh and the fragments,
+/// drawn with a single instanced call per fragment. Each instance is
+/// culled separately, and the visible ones are packed into the instance
+/// buffer, which is attached to the group mesh.
+pub struct Group<R: gfx::Resources, M, T, B> {
+    /// Mesh with the instance attributes attached.
+    mesh: gfx::Mesh<R>,
+    /// Original mesh.
+    base: gfx::Mesh<R>,
+    buffer: gfx::handle::Buffer<R, Instance>,
+    capacity: usize,
+    /// Drawable fragments of each instance.
+    pub fragments: Vec<::Fragment<R, M>>,
+    /// Instance local -> world transforms.
+    pub transforms: Vec<T>,
+    /// Local bound of a single instance.
+    pub bound: B,
+    /// Visibility of the whole group.
+    pub visible: bool,
+    /// Render layers of the whole group.
+    pub layers: ::Layers,
+    /// Whether the instances cast shadows.
+    pub shadow_caster: bool,
+}
+
+impl<R: gfx::Resources, M, T, B> Group<R, M, T, B> {
+    /// Create a new group, allocating the instance buffer.
+    pub fn new<F: gfx::Factory<R>>(factory: &mut F, mesh: gfx::Mesh<R>,
+               fragments: Vec<::Fragment<R, M>>, transforms: Vec<T>, bound: B)
+               -> Group<R, M, T, B> {
+        let capacity = transforms.len();
+        let buffer = factory.create_buffer_dynamic::<Instance>(capacity, gfx::BufferRole::Vertex);
+        let mut full = mesh.clone();
+        full.attributes.extend(get_attributes(&buffer).into_iter());
+        Group {
+            mesh: full,
+            base: mesh,
+            buffer: buffer,
+            capacity: capacity,
+            fragments: fragments,
+            transforms: transforms,
+            bound: bound,
+            visible: true,
+            layers: ::LAYER_DEFAULT,
+            shadow_caster: true,
+        }
+    }
+
+    /// Grow the instance buffer to fit all the transforms. Needs to be
+    /// called after adding transforms, otherwise the extra instances
+    /// are not drawn.
+    pub fn reserve<F: gfx::Factory<R>>(&mut self, factory: &mut F) {
+        if self.transforms.len() <= self.capacity {
+            return
+        }
+        self.capacity = self.transforms.len();
+        self.buffer = factory.create_buffer_dynamic::<Instance>(self.capacity, gfx::BufferRole::Vertex);
+        self.mesh = self.base.clone();
+        self.mesh.attributes.extend(get_attributes(&self.buffer).into_iter());
+    }
+
+    /// Get the mesh with the instance attributes.
+    pub fn get_mesh(&self) -> &gfx::Mesh<R> {
+        &self.mesh
+    }
+}
+
+impl<'u,
+    B: collision::Bound<f32>,
+    T: cgmath::Transform3<f32> + Clone,
+    U: Culler<f32, B>,
+> Context<'u, f32, B, T, U> {
+    /// Cull the instances of the groups, upload the visible ones, and draw
+    /// them into a stream. The uploads are recorded into the stream, so
+    /// each pass draws its own instances. The view information is built
+    /// with the model transform of the visible instance closest to their
+    /// centroid, for the group to be sorted by depth where its instances
+    /// are, and the uploaded matrices are relative to that transform.
+    /// The view information also receives the number of visible instances.
+    pub fn draw_instanced<'b, R, M, I, V, H, X>(&mut self,
+                          groups: I, phase: &mut H, stream: &mut X)
+                          -> Result<::Report, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        T: 'b,
+        B: 'b,
+        I: Iterator<Item = &'b Group<R, M, T, B>>,
+        V: ::ViewInfo<f32, T> + InstanceInfo,
+        H: ::AbstractPhase<R, M, V>,
+        X: gfx::Stream<R>,
+    {
+        use cgmath::{Matrix, Transform};
+        let mut report = ::Report::new();
+        let mut visible: Vec<(usize, cgmath::Matrix4<f32>)> = Vec::new();
+        let mut data: Vec<Instance> = Vec::new();
+
+        let g = hprof::enter("enqueue");
+        for group in groups {
+            let frag_count = group.fragments.len() as ::Count;
+            if !group.visible {
+                report.calls_invisible += frag_count;
+                continue
+            }
+            if self.is_excluded(group.layers, group.shadow_caster) {
+                report.calls_masked += frag_count;
+                continue
+            }
+            visible.clear();
+            let num = group.capacity.min(group.transforms.len());
+            for (i, model) in group.transforms[..num].iter().enumerate() {
+                let mx: cgmath::Matrix4<f32> = model.clone().into();
+                let mvp = self.get_view_projection().mul_m(&mx);
+                if self.cull_mvp(&group.bound, &mvp) != collision::Relation::Out {
+                    visible.push((i, mx));
+                }
+            }
+            if visible.is_empty() {
+                report.calls_culled += frag_count;
+                continue
+            }
+            // the reference instance is the closest one to the centroid
+            let scale = 1.0 / visible.len() as f32;
+            let mut centroid = [0.0f32; 3];
+            for &(_, ref mx) in visible.iter() {
+                for k in 0..3 {
+                    centroid[k] += mx[3][k] * scale;
+                }
+            }
+            let distance = |mx: &cgmath::Matrix4<f32>| (0..3).fold(0.0, |sum, k| {
+                let d = mx[3][k] - centroid[k];
+                sum + d * d
+            });
+            let mut reference = 0;
+            for (k, &(_, ref mx)) in visible.iter().enumerate() {
+                if distance(mx) < distance(&visible[reference].1) {
+                    reference = k;
+                }
+            }
+            let model = group.transforms[visible[reference].0].clone();
+            let (model, inverse): (T, cgmath::Matrix4<f32>) = match model.invert() {
+                Some(inv) => (model, inv.into()),
+                None => (T::one(), cgmath::Matrix4::identity()),
+            };
+            data.clear();
+            data.extend(visible.iter().map(|&(_, ref mx)| inverse.mul_m(mx).into()));
+            stream.access().0.update_buffer_vec(&group.buffer, &data, 0);
+            let mvp = self.get_view_projection().mul_m(&model.clone().into());
+            let view = self.get_camera_inverse().concat(&model);
+            let mut view_info: V = ::ViewInfo::new(mvp, view, model);
+            view_info.set_instances(data.len() as gfx::InstanceCount);
+            // every passed call renders the primitives of all the instances
+            let primitives = report.primitives_rendered;
+            try!(enqueue_fragments(phase, &group.mesh, &group.fragments,
+                                   &view_info, &mut report));
+            let added = report.primitives_rendered - primitives;
+            report.primitives_rendered += added * (data.len() as ::Count - 1);
+        }
+        drop(g);
+
+        let _g = hprof::enter("flush");
+        match phase.flush(stream) {
+            Ok(()) => Ok(report),
+            Err(e) => Err(::Error::Flush(e)),
+        }
+    }
+}
@@ -7,11 +7,35 @@ extern crate gfx;
 extern crate cgmath;
 extern crate collision;
 extern crate hprof;
-
+#[cfg(feature = "parallel")]
+extern crate rayon;
+
+pub mod anim;
+pub mod bound;
+pub mod camera;
+pub mod cluster;
+pub mod instance;
+pub mod light;
+pub mod lod;
+pub mod pick;
+pub mod query;
+pub mod relative;
+pub mod shadow;
+pub mod skin;
+pub mod sprite;
+
+mod batch;
 mod cull;
+mod group;
+mod multi;
 
-pub use gfx_phase::AbstractPhase;
-pub use self::cull::{Culler, Frustum, Context};
+pub use gfx_phase::{AbstractPhase, SharedPhase};
+pub use self::batch::BatchFrustum;
+pub use self::cull::{Culler, Frustum, Temporal, Context, Placement, Hook, Fix,
+                     get_clip_planes};
+pub use self::group::PhaseGroup;
+pub use self::multi::{ViewMask, MAX_VIEWS, MultiContext};
+pub use self::relative::{RelativeCamera, RelativeContext};
 
 /// Scene drawing error.
 #[derive(Debug)]
@@ -25,11 +49,21 @@ pub enum Error {
 /// Type of the call counter.
 pub type Count = u32;
 
+/// Bit mask of render layers, e.g. "world", "UI", or "reflection only".
+/// An entity is drawn in a pass if their masks have a common bit.
+pub type Layers = u32;
+/// Mask of all the layers.
+pub const LAYER_ALL: Layers = !0;
+/// Layer of entities that don't specify any.
+pub const LAYER_DEFAULT: Layers = 1;
+
 /// Rendering success report.
 #[derive(Clone, Debug)]
 pub struct Report {
     /// Number of calls in invisible entities.
     pub calls_invisible: Count,
+    /// Number of calls in entities outside of the pass layers.
+    pub calls_masked: Count,
     /// Number of calls that got culled out.
     pub calls_culled: Count,
     /// Number of calls that the phase doesn't apply to.
@@ -40,6 +74,8 @@ pub struct Report {
     pub calls_passed: Count,
     /// Number of primitives rendered.
     pub primitives_rendered: Count,
+    /// Number of drawn entities per level of detail.
+    pub lod_levels: Vec<Count>,
 }
 
 impl Report {
@@ -50,16 +86,40 @@ impl Report {
             calls_failed: 0,
             calls_culled: 0,
             calls_invisible: 0,
+            calls_masked: 0,
             calls_passed: 0,
             primitives_rendered: 0,
+            lod_levels: Vec::new(),
+        }
+    }
+
+    /// Accumulate the counters of another report.
+    pub fn merge(&mut self, other: &Report) {
+        self.calls_invisible += other.calls_invisible;
+        self.calls_masked += other.calls_masked;
+        self.calls_culled += other.calls_culled;
+        self.calls_rejected += other.calls_rejected;
+        self.calls_failed += other.calls_failed;
+        self.calls_passed += other.calls_passed;
+        self.primitives_rendered += other.primitives_rendered;
+        for (i, &num) in other.lod_levels.iter().enumerate() {
+            self.add_lod(i, num);
         }
     }
 
+    /// Count drawn entities of a given level of detail.
+    pub fn add_lod(&mut self, level: usize, num: Count) {
+        if self.lod_levels.len() <= level {
+            self.lod_levels.resize(level + 1, 0);
+        }
+        self.lod_levels[level] += num;
+    }
+
     /// Get total number of draw calls.
     pub fn get_calls_total(&self) -> Count {
-        self.calls_invisible + self.calls_culled +
-        self.calls_rejected  + self.calls_failed +
-        self.calls_passed
+        self.calls_invisible + self.calls_masked +
+        self.calls_culled + self.calls_rejected +
+        self.calls_failed + self.calls_passed
     }
 
     /// Get the rendered/submitted calls ratio.
@@ -120,6 +180,12 @@ pub trait Entity<R: gfx::Resources, M>: Node {
     type Bound;
     /// Check if it's visible.
     fn is_visible(&self) -> bool { true }
+    /// Get the render layers.
+    fn get_layers(&self) -> Layers { LAYER_DEFAULT }
+    /// Check if it casts shadows.
+    fn is_shadow_caster(&self) -> bool { true }
+    /// Check if it receives shadows.
+    fn is_shadow_receiver(&self) -> bool { true }
     /// Get the local bound.
     fn get_bound(&self) -> Self::Bound;
     /// Get the mesh.
@@ -134,6 +200,12 @@ pub trait Camera<S>: Node {
     type Projection: std::convert::Into<cgmath::Matrix4<S>>;
     /// Get the projection.
     fn get_projection(&self) -> Self::Projection;
+    /// Get the clip space depth convention of the projection, which the
+    /// contexts configure their cullers with. The standard GL one by
+    /// default.
+    fn get_depth_range(&self) -> camera::DepthRange {
+        camera::DepthRange::NegativeOneToOne
+    }
     /// Compute the view-projection matrix.
     fn get_view_projection(&self) -> cgmath::Matrix4<S> where
         S: cgmath::BaseFloat,
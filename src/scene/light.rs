@@ -0,0 +1,248 @@
+//! Light sources and their assignment to entities.
+
+use cgmath;
+use cgmath::{Point3, Rad, Vector3};
+use collision;
+use collision::Sphere;
+use gfx;
+use bound::{ToSphere, Transformable};
+use cull::{Culler, Context};
+use camera::Transform;
+
+/// Maximum number of lights affecting a single entity.
+pub const MAX_LIGHTS: usize = 8;
+
+/// Type of a light source.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind<S> {
+    /// Omni-directional light.
+    Point,
+    /// Cone light, shining along the local -Z axis.
+    Spot {
+        /// Angle where the falloff starts, from the cone axis.
+        inner: Rad<S>,
+        /// Angle of the cone boundary, from the cone axis.
+        outer: Rad<S>,
+    },
+    /// Infinitely far light, shining along the local -Z axis.
+    Directional,
+}
+
+/// A light source.
+#[derive(Clone, Debug)]
+pub struct Light<S> {
+    /// Light type.
+    pub kind: Kind<S>,
+    /// Linear RGB color.
+    pub color: [S; 3],
+    /// Intensity multiplier.
+    pub intensity: S,
+    /// Distance where the light fades out completely, ignored by
+    /// directional lights.
+    pub range: S,
+    /// Light -> world transform.
+    pub transform: Transform<S>,
+    /// Visibility flag.
+    pub visible: bool,
+}
+
+impl<S: cgmath::BaseFloat> Light<S> {
+    /// Create a new white light at the origin.
+    pub fn new(kind: Kind<S>, intensity: S, range: S) -> Light<S> {
+        Light {
+            kind: kind,
+            color: [S::one(); 3],
+            intensity: intensity,
+            range: range,
+            transform: cgmath::Transform::one(),
+            visible: true,
+        }
+    }
+
+    /// Get the world space position.
+    pub fn get_position(&self) -> Point3<S> {
+        use cgmath::Point;
+        Point3::from_vec(&self.transform.disp)
+    }
+
+    /// Get the world space direction of the light rays.
+    pub fn get_direction(&self) -> Vector3<S> {
+        use cgmath::{EuclideanVector, Rotation};
+        let zero = S::zero();
+        self.transform.rot.rotate_vector(&Vector3::new(zero, zero, -S::one()))
+            .normalize()
+    }
+
+    /// Get the local bound of the lit volume, `None` for directional lights.
+    pub fn get_bound(&self) -> Option<Sphere<S>> {
+        match self.kind {
+            Kind::Directional => None,
+            Kind::Point | Kind::Spot {..} => Some(Sphere {
+                center: Point3::new(S::zero(), S::zero(), S::zero()),
+                radius: self.range,
+            }),
+        }
+    }
+
+    /// Estimate the light contribution to a world space sphere. Zero means
+    /// the sphere is not affected at all.
+    pub fn get_influence(&self, sphere: &Sphere<S>) -> S {
+        use cgmath::{EuclideanVector, Point};
+        let (zero, one) = (S::zero(), S::one());
+        let (inner, outer) = match self.kind {
+            Kind::Directional => return self.intensity,
+            Kind::Point => (None, None),
+            Kind::Spot { inner, outer } => (Some(inner), Some(outer)),
+        };
+        let range = self.range * self.transform.scale;
+        let offset = sphere.center.sub_p(&self.get_position());
+        let distance = offset.length();
+        let gap = (distance - sphere.radius).max(zero);
+        if gap >= range {
+            return zero
+        }
+        let mut cone = one;
+        if let (Some(inner), Some(outer)) = (inner, outer) {
+            if distance > sphere.radius {
+                let angle = (offset.div_s(distance).dot(&self.get_direction()))
+                            .max(-one).min(one).acos();
+                let half = (sphere.radius / distance).asin();
+                let angle = (angle - half).max(zero);
+                if angle >= outer.s {
+                    return zero
+                }
+                if angle > inner.s {
+                    cone = (outer.s - angle) / (outer.s - inner.s).max(S::epsilon());
+                }
+            }
+        }
+        // smooth window over the range, inverse square falloff
+        let ratio = gap / range;
+        let window = (one - ratio * ratio).max(zero);
+        self.intensity * cone * window * window / (one + gap * gap)
+    }
+}
+
+impl<S: cgmath::BaseFloat> ::Node for Light<S> {
+    type Transform = Transform<S>;
+    fn get_transform(&self) -> Transform<S> {
+        self.transform.clone()
+    }
+}
+
+/// Cull the lights against a view-projection matrix, collecting the
+/// indices of the visible ones into `out`.
+pub fn cull<S, U>(culler: &mut U, view_projection: &cgmath::Matrix4<S>,
+                  lights: &[Light<S>], out: &mut Vec<usize>) where
+    S: cgmath::BaseFloat,
+    U: Culler<S, Sphere<S>>,
+{
+    use cgmath::Matrix;
+    culler.init();
+    for (i, light) in lights.iter().enumerate() {
+        if !light.visible {
+            continue
+        }
+        let bound = match light.get_bound() {
+            Some(b) => b,
+            None => {
+                out.push(i);
+                continue
+            },
+        };
+        let model: cgmath::Matrix4<S> = light.transform.clone().into();
+        let mvp = view_projection.mul_m(&model);
+        if culler.cull(&bound, &mvp) != collision::Relation::Out {
+            out.push(i);
+        }
+    }
+}
+
+/// List of light indices affecting an entity, most influential first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LightList {
+    indices: [u16; MAX_LIGHTS],
+    count: u8,
+}
+
+impl LightList {
+    /// Create an empty list.
+    pub fn new() -> LightList {
+        LightList {
+            indices: [0; MAX_LIGHTS],
+            count: 0,
+        }
+    }
+
+    /// Get the light indices.
+    pub fn get_indices(&self) -> &[u16] {
+        &self.indices[.. self.count as usize]
+    }
+
+    /// Select up to `max` (at most `MAX_LIGHTS`) lights with the highest
+    /// influence on a world space sphere, out of the given candidates.
+    /// Panics if there are more lights than the `u16` indices can address.
+    pub fn select<S: cgmath::BaseFloat>(lights: &[Light<S>], candidates: &[usize],
+                  sphere: &Sphere<S>, max: usize) -> LightList {
+        assert!(lights.len() <= ::std::u16::MAX as usize + 1, "Too many lights: {}", lights.len());
+        let max = max.min(MAX_LIGHTS);
+        let mut list = LightList::new();
+        let mut weights = [S::zero(); MAX_LIGHTS];
+        for &i in candidates.iter() {
+            let w = lights[i].get_influence(sphere);
+            if w <= S::zero() {
+                continue
+            }
+            let count = list.count as usize;
+            // find the insertion point, keeping the list sorted
+            let pos = weights[.. count].iter().position(|&x| x < w).unwrap_or(count);
+            if pos >= max {
+                continue
+            }
+            let end = (count + 1).min(max);
+            for j in (pos + 1 .. end).rev() {
+                weights[j] = weights[j - 1];
+                list.indices[j] = list.indices[j - 1];
+            }
+            weights[pos] = w;
+            list.indices[pos] = i as u16;
+            list.count = end as u8;
+        }
+        list
+    }
+}
+
+/// View information able to carry the lights affecting an entity, to be
+/// consumed by `Technique::fix_params` of forward techniques.
+pub trait LightInfo {
+    /// Set the affecting lights, as indices into the light slice.
+    fn set_lights(&mut self, LightList);
+}
+
+impl<'u,
+    S: cgmath::BaseFloat,
+    B: collision::Bound<S> + ToSphere<S>,
+    T: cgmath::Transform3<S> + Clone,
+    U: Culler<S, B>,
+> Context<'u, S, B, T, U> {
+    /// Cull and draw the entities into a stream, passing the most
+    /// influential of the visible lights, as returned by `light::cull`,
+    /// through the view information.
+    pub fn draw_lit<'b, R, M, E, I, V, H, X>(&mut self,
+                    entities: I, lights: &[Light<S>], visible: &[usize],
+                    max_lights: usize, phase: &mut H, stream: &mut X)
+                    -> Result<::Report, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: ::Entity<R, M, Bound = B, Transform = T> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<S, T> + LightInfo,
+        H: ::AbstractPhase<R, M, V>,
+        X: gfx::Stream<R>,
+    {
+        self.draw_with(entities, |ent: &E, view_info: &mut V| {
+            let sphere = ent.get_bound().to_sphere().transform(&ent.get_transform());
+            view_info.set_lights(LightList::select(lights, visible, &sphere, max_lights));
+        }, phase, stream)
+    }
+}
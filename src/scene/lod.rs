@@ -0,0 +1,352 @@
+//! Level of detail selection.
+
+use cgmath;
+use collision;
+use gfx;
+use bound::get_max_scale;
+use cull::{Culler, Context, Hook, Placement};
+
+/// Metric that the detail levels are switched by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// Distance from the camera to the entity origin. Level `i + 1` is
+    /// used when the distance exceeds the threshold `i`.
+    Distance,
+    /// Projected size of the entity bounding sphere, as a fraction of the
+    /// viewport height. Level `i + 1` is used when the size falls below
+    /// the threshold `i`.
+    ScreenSize,
+}
+
+/// An entity with several levels of detail. Level 0 is the most detailed,
+/// expected to match `Entity::get_fragments`.
+pub trait LodEntity<R: gfx::Resources, M, S>: ::Entity<R, M> {
+    /// Get the number of levels.
+    fn get_lod_count(&self) -> usize;
+    /// Get the drawable fragments of a given level.
+    fn get_lod_fragments(&self, usize) -> &[::Fragment<R, M>];
+    /// Get the threshold of switching from a given level to the next one.
+    fn get_lod_threshold(&self, usize) -> S;
+    /// Get the radius of the local bounding sphere.
+    fn get_lod_radius(&self) -> S;
+}
+
+/// View information able to carry the cross-fade factor of a level
+/// transition, to be consumed by `Technique::fix_params`, e.g. for
+/// dithered blending between the levels.
+pub trait FadeInfo<S> {
+    /// Set the fade factor. The incoming level receives a value growing
+    /// from 0 to 1, the outgoing level receives the same value minus one,
+    /// so the dithering patterns of both can be complementary. Entities
+    /// outside of transitions receive 1.
+    fn set_fade(&mut self, S);
+}
+
+/// Transition between two levels.
+#[derive(Clone, Copy, Debug)]
+struct Transition<S> {
+    from: usize,
+    progress: S,
+}
+
+/// Level of detail selector. It remembers the last level of each entity,
+/// recognized by the order of entities, in order to apply the hysteresis
+/// and track the cross-fade transitions.
+pub struct Selector<S> {
+    /// Switching metric.
+    pub metric: Metric,
+    /// Relative width of the hysteresis band around thresholds, e.g. 0.1
+    /// delays the switch until the metric is 10% beyond the threshold.
+    pub hysteresis: S,
+    /// Duration of cross-fade transitions, zero for hard switching.
+    pub fade_time: S,
+    levels: Vec<Option<usize>>,
+    transitions: Vec<Option<Transition<S>>>,
+}
+
+impl<S: cgmath::BaseFloat> Selector<S> {
+    /// Create a new selector with no hysteresis.
+    pub fn new(metric: Metric) -> Selector<S> {
+        Selector {
+            metric: metric,
+            hysteresis: S::zero(),
+            fade_time: S::zero(),
+            levels: Vec::new(),
+            transitions: Vec::new(),
+        }
+    }
+
+    /// Enable cross-fade transitions of a given duration.
+    pub fn with_fade(self, fade_time: S) -> Selector<S> {
+        Selector {
+            fade_time: fade_time,
+            .. self
+        }
+    }
+
+    /// Advance the transitions by a time step, finishing the complete ones.
+    pub fn advance(&mut self, delta: S) {
+        let step = if self.fade_time > S::zero() {
+            delta / self.fade_time
+        } else {
+            S::one()
+        };
+        for tr in self.transitions.iter_mut() {
+            let done = match *tr {
+                Some(ref mut t) => {
+                    t.progress = t.progress + step;
+                    t.progress >= S::one()
+                },
+                None => false,
+            };
+            if done {
+                *tr = None;
+            }
+        }
+    }
+
+    /// Get the transition of an entity, as the outgoing level and the
+    /// progress from 0 to 1.
+    pub fn get_transition(&self, slot: usize) -> Option<(usize, S)> {
+        self.transitions.get(slot).and_then(|t| *t)
+            .map(|t| (t.from, t.progress))
+    }
+
+    /// Set the hysteresis band.
+    pub fn with_hysteresis(self, hysteresis: S) -> Selector<S> {
+        Selector {
+            hysteresis: hysteresis,
+            .. self
+        }
+    }
+
+    /// Forget the remembered levels and transitions.
+    pub fn reset(&mut self) {
+        self.levels.clear();
+        self.transitions.clear();
+    }
+
+    /// Get the last selected level of an entity.
+    pub fn get_level(&self, slot: usize) -> Option<usize> {
+        self.levels.get(slot).and_then(|l| *l)
+    }
+
+    /// Compute the metric value, given the model-view transform, the
+    /// model-view-projection matrix, the projection matrix, and the
+    /// bounding radius in the model space, which is scaled by the largest
+    /// scale of the model-view transform.
+    pub fn measure<T>(&self, view: &T, mvp: &cgmath::Matrix4<S>,
+                      projection: &cgmath::Matrix4<S>, radius: S) -> S where
+        T: cgmath::Transform3<S>,
+    {
+        use cgmath::{EuclideanVector, Point};
+        match self.metric {
+            Metric::Distance => {
+                let zero = S::zero();
+                view.transform_point(&cgmath::Point3::new(zero, zero, zero))
+                    .to_vec().length()
+            },
+            Metric::ScreenSize => {
+                let (zero, one) = (S::zero(), S::one());
+                let scale = get_max_scale(
+                    &view.transform_vector(&cgmath::Vector3::new(one, zero, zero)),
+                    &view.transform_vector(&cgmath::Vector3::new(zero, one, zero)),
+                    &view.transform_vector(&cgmath::Vector3::new(zero, zero, one)));
+                let w = mvp[3][3].max(S::epsilon());
+                radius * scale * projection[1][1] / w
+            },
+        }
+    }
+
+    fn is_coarser(&self, value: S, threshold: S) -> bool {
+        let one = S::one();
+        match self.metric {
+            Metric::Distance => value > threshold * (one + self.hysteresis),
+            Metric::ScreenSize => value < threshold * (one - self.hysteresis),
+        }
+    }
+
+    fn is_finer(&self, value: S, threshold: S) -> bool {
+        let one = S::one();
+        match self.metric {
+            Metric::Distance => value < threshold * (one - self.hysteresis),
+            Metric::ScreenSize => value > threshold * (one + self.hysteresis),
+        }
+    }
+
+    /// Select the level of an entity in a given slot, by the metric value.
+    /// `threshold(i)` gives the switching threshold from level `i` to `i+1`.
+    pub fn select<F>(&mut self, slot: usize, value: S, count: usize, threshold: F)
+                     -> usize where
+        F: Fn(usize) -> S,
+    {
+        if self.levels.len() <= slot {
+            self.levels.resize(slot + 1, None);
+        }
+        let mut level = match self.levels[slot] {
+            Some(l) if l < count => l,
+            // no history: start from the top, with no hysteresis
+            _ => {
+                let mut l = 0;
+                while l + 1 < count && match self.metric {
+                    Metric::Distance => value > threshold(l),
+                    Metric::ScreenSize => value < threshold(l),
+                } {
+                    l += 1;
+                }
+                l
+            },
+        };
+        while level + 1 < count && self.is_coarser(value, threshold(level)) {
+            level += 1;
+        }
+        while level > 0 && self.is_finer(value, threshold(level - 1)) {
+            level -= 1;
+        }
+        if self.transitions.len() <= slot {
+            self.transitions.resize(slot + 1, None);
+        }
+        match self.levels[slot] {
+            Some(prev) if prev != level && prev < count && self.fade_time > S::zero() => {
+                self.transitions[slot] = Some(Transition {
+                    from: prev,
+                    progress: S::zero(),
+                });
+            },
+            _ => (),
+        }
+        if let Some(t) = self.transitions[slot] {
+            if t.from >= count {
+                self.transitions[slot] = None;
+            }
+        }
+        self.levels[slot] = Some(level);
+        level
+    }
+}
+
+/// Select the level of a visible entity, counting it in the report.
+fn choose<R, M, E, S, T>(selector: &mut Selector<S>, entity: &E,
+                         place: &Placement<S, T>, report: &mut ::Report)
+                         -> usize where
+    R: gfx::Resources,
+    E: LodEntity<R, M, S>,
+    S: cgmath::BaseFloat,
+    T: cgmath::Transform3<S>,
+{
+    let value = selector.measure(place.view, place.mvp, place.projection,
+                                 entity.get_lod_radius());
+    let level = selector.select(place.slot, value, entity.get_lod_count(),
+                                |i| entity.get_lod_threshold(i));
+    report.add_lod(level, 1);
+    level
+}
+
+/// Count the calls of a skipped entity by its last selected level.
+fn count_level<R, M, E, S>(selector: &Selector<S>, slot: usize, entity: &E)
+                           -> ::Count where
+    R: gfx::Resources,
+    E: LodEntity<R, M, S>,
+{
+    let level = selector.get_level(slot).unwrap_or(0);
+    let last = entity.get_lod_count().saturating_sub(1);
+    entity.get_lod_fragments(level.min(last)).len() as ::Count
+}
+
+/// Drawing hook selecting the level of detail of each entity.
+pub struct Levels<'a, S: 'a>(pub &'a mut Selector<S>);
+
+impl<'a, 'b, R, M, E, S, T, V> Hook<'b, R, M, E, S, T, V> for Levels<'a, S> where
+    R: gfx::Resources + 'b,
+    M: 'b,
+    E: LodEntity<R, M, S> + 'b,
+    S: cgmath::BaseFloat,
+    T: cgmath::Transform3<S>,
+{
+    fn count(&self, slot: usize, entity: &'b E) -> ::Count {
+        count_level(self.0, slot, entity)
+    }
+
+    fn select(&mut self, entity: &'b E, place: &Placement<S, T>, view_info: V,
+              parts: &mut Vec<(&'b [::Fragment<R, M>], V)>, report: &mut ::Report) {
+        let level = choose(self.0, entity, place, report);
+        parts.push((entity.get_lod_fragments(level), view_info));
+    }
+}
+
+/// Drawing hook selecting the level of detail of each entity and
+/// cross-fading between the levels.
+pub struct FadeLevels<'a, S: 'a>(pub &'a mut Selector<S>);
+
+impl<'a, 'b, R, M, E, S, T, V> Hook<'b, R, M, E, S, T, V> for FadeLevels<'a, S> where
+    R: gfx::Resources + 'b,
+    M: 'b,
+    E: LodEntity<R, M, S> + 'b,
+    S: cgmath::BaseFloat,
+    T: cgmath::Transform3<S>,
+    V: FadeInfo<S> + Clone,
+{
+    fn count(&self, slot: usize, entity: &'b E) -> ::Count {
+        count_level(self.0, slot, entity)
+    }
+
+    fn select(&mut self, entity: &'b E, place: &Placement<S, T>, mut view_info: V,
+              parts: &mut Vec<(&'b [::Fragment<R, M>], V)>, report: &mut ::Report) {
+        let level = choose(self.0, entity, place, report);
+        if let Some((from, progress)) = self.0.get_transition(place.slot) {
+            let mut old_info = view_info.clone();
+            old_info.set_fade(progress - S::one());
+            parts.push((entity.get_lod_fragments(from), old_info));
+            view_info.set_fade(progress);
+        } else {
+            view_info.set_fade(S::one());
+        }
+        parts.push((entity.get_lod_fragments(level), view_info));
+    }
+}
+
+impl<'u,
+    S: cgmath::BaseFloat,
+    B: collision::Bound<S>,
+    T: cgmath::Transform3<S> + Clone,
+    U: Culler<S, B>,
+> Context<'u, S, B, T, U> {
+    /// Cull and draw the entities into a stream, selecting the level
+    /// of detail of each. The entities are expected to come in a stable
+    /// order between frames. The report counts entities per level, and
+    /// the calls of the skipped entities by their last selected level.
+    pub fn draw_lod<'b, R, M, E, I, V, H, X>(&mut self,
+                    entities: I, selector: &mut Selector<S>,
+                    phase: &mut H, stream: &mut X)
+                    -> Result<::Report, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: LodEntity<R, M, S, Bound = B, Transform = T> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<S, T>,
+        H: ::AbstractPhase<R, M, V>,
+        X: gfx::Stream<R>,
+    {
+        self.draw_hooked(entities, &mut Levels(selector), phase, stream)
+    }
+
+    /// Cull and draw the entities into a stream, selecting the level of
+    /// detail of each and cross-fading between the levels. During a
+    /// transition both levels are enqueued, with the fade factors set in
+    /// the view information. `Selector::advance` is expected to be called
+    /// once per frame.
+    pub fn draw_lod_fade<'b, R, M, E, I, V, H, X>(&mut self,
+                         entities: I, selector: &mut Selector<S>,
+                         phase: &mut H, stream: &mut X)
+                         -> Result<::Report, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: LodEntity<R, M, S, Bound = B, Transform = T> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<S, T> + FadeInfo<S> + Clone,
+        H: ::AbstractPhase<R, M, V>,
+        X: gfx::Stream<R>,
+    {
+        self.draw_hooked(entities, &mut FadeLevels(selector), phase, stream)
+    }
+}
@@ -0,0 +1,191 @@
+//! Multi-view culling and drawing.
+
+use std::marker::PhantomData;
+use cgmath;
+use collision;
+use gfx;
+use hprof;
+use camera::DepthRange;
+use cull::{Culler, Filter};
+
+/// Bit mask of the views an entity is visible in.
+pub type ViewMask = u32;
+
+/// Maximum number of views in a `MultiContext`.
+pub const MAX_VIEWS: usize = 32;
+
+struct View<S, T> {
+    cam_inverse: T,
+    view_projection: cgmath::Matrix4<S>,
+    depth: DepthRange,
+    layers: ::Layers,
+}
+
+/// Culler context of several views (shadow cascades, cube map faces,
+/// split-screen players, etc). Each entity is culled against all the views
+/// in a single pass, and its fragments are enqueued into the phases of
+/// the views it's visible in.
+pub struct MultiContext<'u, S, B, T, U> where
+    S: cgmath::BaseFloat,
+    B: collision::Bound<S>,
+    U: Culler<S, B> + 'u,
+{
+    culler: &'u mut U,
+    views: Vec<View<S, T>>,
+    mvps: Vec<cgmath::Matrix4<S>>,
+    filter: Filter,
+    dummy: PhantomData<B>,
+}
+
+impl<'u,
+    S: cgmath::BaseFloat,
+    B: collision::Bound<S>,
+    T: cgmath::Transform3<S> + Clone,
+    U: Culler<S, B>,
+> MultiContext<'u, S, B, T, U> {
+    /// Create a new context with no views.
+    pub fn new(culler: &'u mut U) -> MultiContext<'u, S, B, T, U> {
+        culler.init();
+        MultiContext {
+            culler: culler,
+            views: Vec::new(),
+            mvps: Vec::new(),
+            filter: Filter::new(),
+            dummy: PhantomData,
+        }
+    }
+
+    /// Set the layers of all the views. Entities outside of them are skipped.
+    pub fn set_layers(&mut self, layers: ::Layers) {
+        self.filter.layers = layers;
+    }
+
+    /// Only draw the shadow casters, e.g. for shadow cascades.
+    pub fn set_casters_only(&mut self, casters_only: bool) {
+        self.filter.casters_only = casters_only;
+    }
+
+    /// Add a view of a camera, returning its index.
+    pub fn add_view<C>(&mut self, camera: &C) -> usize where
+        C: ::Camera<S, Transform = T>,
+    {
+        self.add_view_masked(camera, ::LAYER_ALL)
+    }
+
+    /// Add a view of a camera, drawing only the given layers, further
+    /// restricted by `set_layers`. Returns the view index.
+    pub fn add_view_masked<C>(&mut self, camera: &C, layers: ::Layers) -> usize where
+        C: ::Camera<S, Transform = T>,
+    {
+        use cgmath::{Matrix, Transform};
+        assert!(self.views.len() < MAX_VIEWS, "Too many views");
+        let cam_inverse = camera.get_transform().invert().unwrap();
+        let mx_proj: cgmath::Matrix4<S> = camera.get_projection().into();
+        let mx_view_proj = mx_proj.mul_m(&cam_inverse.clone().into());
+        self.views.push(View {
+            cam_inverse: cam_inverse,
+            view_projection: mx_view_proj,
+            depth: camera.get_depth_range(),
+            layers: layers,
+        });
+        self.views.len() - 1
+    }
+
+    /// Get the number of views.
+    pub fn get_view_count(&self) -> usize {
+        self.views.len()
+    }
+
+    /// Cull a node against all the views, returning the mask of views
+    /// it's visible in.
+    pub fn cull<N>(&mut self, node: &N, bound: &B) -> ViewMask where
+        N: ::Node<Transform = T>,
+    {
+        use cgmath::Matrix;
+        let model: cgmath::Matrix4<S> = node.get_transform().into();
+        let mut mask = 0;
+        self.mvps.clear();
+        for (i, view) in self.views.iter().enumerate() {
+            let mvp = view.view_projection.mul_m(&model);
+            self.culler.set_depth_range(view.depth);
+            if self.culler.cull(bound, &mvp) != collision::Relation::Out {
+                mask |= 1 << i;
+            }
+            self.mvps.push(mvp);
+        }
+        mask
+    }
+
+    /// Cull and draw the entities into a stream, using a phase per view,
+    /// with phase indices matching the view indices. The phases are flushed
+    /// in order. Returns a report per view, where the entities outside of
+    /// the view layers are counted as masked.
+    pub fn draw<'b, R, M, E, I, V, G, X>(&mut self,
+                entities: I, phases: &mut G, stream: &mut X)
+                -> Result<Vec<::Report>, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: ::Entity<R, M, Bound = B, Transform = T> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<S, T>,
+        G: ::PhaseGroup<R, M, V> + ?Sized,
+        X: gfx::Stream<R>,
+    {
+        use cgmath::Transform;
+        let num = self.views.len();
+        assert!(phases.get_count() >= num, "Not enough phases for the views");
+        let mut reports: Vec<_> = (0..num).map(|_| ::Report::new()).collect();
+
+        let g = hprof::enter("enqueue");
+        for ent in entities {
+            let frag_count = ent.get_fragments().len() as ::Count;
+            if !ent.is_visible() {
+                for rep in reports.iter_mut() {
+                    rep.calls_invisible += frag_count;
+                }
+                continue
+            }
+            let layers = ent.get_layers();
+            let accepted = self.views.iter().any(|v| layers & v.layers != 0);
+            if !accepted || self.filter.rejects(ent) {
+                for rep in reports.iter_mut() {
+                    rep.calls_masked += frag_count;
+                }
+                continue
+            }
+            let mask = self.cull(ent, &ent.get_bound());
+            let model = ent.get_transform();
+            for (i, rep) in reports.iter_mut().enumerate() {
+                if layers & self.views[i].layers == 0 {
+                    rep.calls_masked += frag_count;
+                    continue
+                }
+                if mask & (1 << i) == 0 {
+                    rep.calls_culled += frag_count;
+                    continue
+                }
+                let view = self.views[i].cam_inverse.concat(&model);
+                let view_info: V = ::ViewInfo::new(self.mvps[i], view, model.clone());
+                for frag in ent.get_fragments().iter() {
+                    match phases.enqueue(i, ent.get_mesh(), &frag.slice, &frag.material, &view_info) {
+                        Ok(true)  => {
+                            rep.primitives_rendered += frag.slice.get_prim_count();
+                            rep.calls_passed += 1;
+                        },
+                        Ok(false) => rep.calls_rejected += 1,
+                        Err(e)    => return Err(::Error::Batch(e)),
+                    }
+                }
+            }
+        }
+        drop(g);
+
+        let _g = hprof::enter("flush");
+        for i in 0..num {
+            if let Err(e) = phases.flush(i, stream) {
+                return Err(::Error::Flush(e))
+            }
+        }
+        Ok(reports)
+    }
+}
@@ -0,0 +1,214 @@
+//! Ray casting and picking of entities.
+
+use std::cmp::Ordering;
+use cgmath;
+use cgmath::{Point3, Vector3};
+use collision::{Aabb3, Ray3, Sphere};
+use gfx;
+
+/// Convert window coordinates (in pixels, Y going down) into normalized
+/// device coordinates.
+pub fn screen_to_ndc<S: cgmath::BaseFloat>(x: S, y: S, width: S, height: S) -> (S, S) {
+    let (one, two) = (S::one(), S::from(2).unwrap());
+    (two * x / width - one, one - two * y / height)
+}
+
+/// Cast a world space ray from the camera through a point given in
+/// normalized device coordinates. The ray starts in the plane of the
+/// camera and has a unit direction, so ray parameters are distances.
+pub fn unproject<S, C>(camera: &C, x: S, y: S) -> Ray3<S> where
+    S: cgmath::BaseFloat,
+    C: ::Camera<S>,
+    C::Transform: cgmath::Transform3<S>,
+{
+    use cgmath::{EuclideanVector, Matrix, Point, Transform, Vector, Vector4};
+    let inverse = camera.get_view_projection().invert().unwrap();
+    // both depths are finite under any depth convention
+    let get_point = |z: f32| {
+        let v = inverse.mul_v(&Vector4::new(x, y, S::from(z).unwrap(), S::one()));
+        Point3::new(v.x / v.w, v.y / v.w, v.z / v.w)
+    };
+    let (a, b) = (get_point(0.25), get_point(0.5));
+    let transform = camera.get_transform();
+    let eye = transform.transform_point(&Point3::new(S::zero(), S::zero(), S::zero()));
+    let forward = transform.transform_vector(&Vector3::new(S::zero(), S::zero(), -S::one()));
+    let mut dir = b.sub_p(&a).normalize();
+    if dir.dot(&forward) < S::zero() {
+        dir = dir.neg_v();
+    }
+    // move the origin back to the camera plane
+    let t = a.sub_p(&eye).dot(&forward) / dir.dot(&forward);
+    Ray3::new(a.add_v(&dir.mul_s(-t)), dir)
+}
+
+/// A bound that can be intersected by a ray. The ray intersections of
+/// `collision` aren't used: this version has none for `Aabb3` (the
+/// `Continuous` and `Discrete` traits only came later), and the one for
+/// `Sphere` expects a unit direction and returns a point behind the origin
+/// of a ray starting inside, while the local rays of `pick_with` are
+/// scaled by the inverse model transform, and need their parameters.
+pub trait RayBound<S> {
+    /// Get the ray parameter of the first intersection, if any.
+    /// Rays starting inside the bound hit it at zero.
+    fn intersect_ray(&self, ray: &Ray3<S>) -> Option<S>;
+}
+
+impl<S: cgmath::BaseFloat> RayBound<S> for Aabb3<S> {
+    fn intersect_ray(&self, ray: &Ray3<S>) -> Option<S> {
+        let (mut t_min, mut t_max) = (S::zero(), S::infinity());
+        let axes = [
+            (ray.origin.x, ray.direction.x, self.min.x, self.max.x),
+            (ray.origin.y, ray.direction.y, self.min.y, self.max.y),
+            (ray.origin.z, ray.direction.z, self.min.z, self.max.z),
+        ];
+        for &(origin, dir, low, high) in axes.iter() {
+            if dir == S::zero() {
+                if origin < low || origin > high {
+                    return None
+                }
+                continue
+            }
+            let (t0, t1) = ((low - origin) / dir, (high - origin) / dir);
+            let (t0, t1) = if t0 < t1 {(t0, t1)} else {(t1, t0)};
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_min > t_max {
+                return None
+            }
+        }
+        Some(t_min)
+    }
+}
+
+impl<S: cgmath::BaseFloat> RayBound<S> for Sphere<S> {
+    fn intersect_ray(&self, ray: &Ray3<S>) -> Option<S> {
+        use cgmath::{Point, Vector};
+        let offset = ray.origin.sub_p(&self.center);
+        let a = ray.direction.dot(&ray.direction);
+        let b = offset.dot(&ray.direction);
+        let c = offset.dot(&offset) - self.radius * self.radius;
+        let disc = b * b - a * c;
+        if disc < S::zero() {
+            return None
+        }
+        let sq = disc.sqrt();
+        let (t0, t1) = ((-b - sq) / a, (-b + sq) / a);
+        if t1 < S::zero() {
+            None
+        } else {
+            Some(t0.max(S::zero()))
+        }
+    }
+}
+
+/// Intersect a ray with a triangle, from either side, returning the
+/// ray parameter of the hit.
+pub fn intersect_triangle<S: cgmath::BaseFloat>(ray: &Ray3<S>, a: &Point3<S>,
+                          b: &Point3<S>, c: &Point3<S>) -> Option<S> {
+    use cgmath::{Point, Vector};
+    let e1 = b.sub_p(a);
+    let e2 = c.sub_p(a);
+    let p = ray.direction.cross(&e2);
+    let det = e1.dot(&p);
+    if det.abs() <= S::epsilon() {
+        return None
+    }
+    let inv = S::one() / det;
+    let s = ray.origin.sub_p(a);
+    let u = s.dot(&p) * inv;
+    if u < S::zero() || u > S::one() {
+        return None
+    }
+    let q = s.cross(&e1);
+    let v = ray.direction.dot(&q) * inv;
+    if v < S::zero() || u + v > S::one() {
+        return None
+    }
+    let t = e2.dot(&q) * inv;
+    if t >= S::zero() {Some(t)} else {None}
+}
+
+/// Picking result.
+#[derive(Clone, Debug)]
+pub struct Hit<S> {
+    /// Index of the entity in the picked sequence.
+    pub entity: usize,
+    /// Index of the fragment, if the triangles were tested.
+    pub fragment: Option<usize>,
+    /// Distance along the ray.
+    pub distance: S,
+    /// World space hit point.
+    pub point: Point3<S>,
+}
+
+/// Cast a ray against the bounds of visible entities in given layers.
+/// Returns the hits sorted by distance.
+pub fn pick<'b, R, M, S, E, I>(ray: &Ray3<S>, entities: I, layers: ::Layers)
+                               -> Vec<Hit<S>> where
+    R: gfx::Resources + 'b,
+    M: 'b,
+    S: cgmath::BaseFloat,
+    E: ::Entity<R, M> + 'b,
+    E::Bound: RayBound<S>,
+    E::Transform: cgmath::Transform3<S>,
+    I: Iterator<Item = &'b E>,
+{
+    pick_with(ray, entities, layers, None::<fn(usize, &E, usize, &Ray3<S>) -> Option<S>>)
+}
+
+/// Cast a ray against the bounds of visible entities in given layers, and
+/// then against the triangles of their fragments, using a user-supplied
+/// function.
+/// The function receives the entity index and reference, the fragment
+/// index and the ray in the local space of the entity, returning the ray
+/// parameter of the closest hit (see `intersect_triangle`).
+/// Returns the hits sorted by distance.
+pub fn pick_with<'b, R, M, S, E, I, F>(ray: &Ray3<S>, entities: I, layers: ::Layers,
+                 mut triangles: Option<F>) -> Vec<Hit<S>> where
+    R: gfx::Resources + 'b,
+    M: 'b,
+    S: cgmath::BaseFloat,
+    E: ::Entity<R, M> + 'b,
+    E::Bound: RayBound<S>,
+    E::Transform: cgmath::Transform3<S>,
+    I: Iterator<Item = &'b E>,
+    F: FnMut(usize, &E, usize, &Ray3<S>) -> Option<S>,
+{
+    use cgmath::{Point, Transform, Vector};
+    let mut hits = Vec::new();
+    for (i, ent) in entities.enumerate() {
+        if !ent.is_visible() || ent.get_layers() & layers == 0 {
+            continue
+        }
+        let model = ent.get_transform();
+        let inverse = match model.invert() {
+            Some(inv) => inv,
+            None => continue,
+        };
+        // affine transforms keep the ray parameters
+        let local = Ray3::new(inverse.transform_point(&ray.origin),
+                              inverse.transform_vector(&ray.direction));
+        let t_bound = match ent.get_bound().intersect_ray(&local) {
+            Some(t) => t,
+            None => continue,
+        };
+        let mut add_hit = |fragment, t: S| hits.push(Hit {
+            entity: i,
+            fragment: fragment,
+            distance: t,
+            point: ray.origin.add_v(&ray.direction.mul_s(t)),
+        });
+        match triangles {
+            Some(ref mut fun) => {
+                for f in 0..ent.get_fragments().len() {
+                    if let Some(t) = fun(i, ent, f, &local) {
+                        add_hit(Some(f), t);
+                    }
+                }
+            },
+            None => add_hit(None, t_bound),
+        }
+    }
+    hits.sort_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap_or(Ordering::Equal));
+    hits
+}
@@ -0,0 +1,189 @@
+//! Spatial queries over entities.
+
+use cgmath;
+use cgmath::{Matrix4, Point3, Vector3, Vector4};
+use collision::{Aabb3, Bound, Relation, Sphere};
+use gfx;
+use camera::DepthRange;
+use bound;
+use cull::{Culler, Frustum, relate_planes};
+
+/// A spatial region to test entity bounds against.
+pub trait Region<S, B> {
+    /// Relate a local bound, placed in the world by a model matrix,
+    /// to the region.
+    fn relate(&self, &B, &Matrix4<S>) -> Relation;
+}
+
+/// A region given by a view-projection matrix, such as a camera frustum.
+#[derive(Clone, Debug)]
+pub struct ClipRegion<S> {
+    /// View-projection matrix.
+    pub matrix: Matrix4<S>,
+    /// Clip space depth convention of the matrix.
+    pub depth: DepthRange,
+}
+
+impl<S> ClipRegion<S> {
+    /// Create a new region, assuming the standard GL depth range.
+    pub fn new(matrix: Matrix4<S>) -> ClipRegion<S> {
+        ClipRegion::with_depth(matrix, DepthRange::NegativeOneToOne)
+    }
+
+    /// Create a new region for a given clip space depth convention.
+    pub fn with_depth(matrix: Matrix4<S>, depth: DepthRange) -> ClipRegion<S> {
+        ClipRegion {
+            matrix: matrix,
+            depth: depth,
+        }
+    }
+}
+
+impl<S: cgmath::BaseFloat, B: Bound<S>> Region<S, B> for ClipRegion<S> {
+    fn relate(&self, bound: &B, model: &Matrix4<S>) -> Relation {
+        use cgmath::Matrix;
+        Frustum::with_depth(self.depth).cull(bound, &self.matrix.mul_m(model))
+    }
+}
+
+impl<S: cgmath::BaseFloat, B: Bound<S>> Region<S, B> for Aabb3<S> {
+    fn relate(&self, bound: &B, model: &Matrix4<S>) -> Relation {
+        use cgmath::Vector;
+        // the six faces as world planes, moved into the model space,
+        // so that flat boxes are still handled
+        let (zero, one) = (S::zero(), S::one());
+        let world = [
+            Vector4::new(one, zero, zero, -self.min.x),
+            Vector4::new(-one, zero, zero, self.max.x),
+            Vector4::new(zero, one, zero, -self.min.y),
+            Vector4::new(zero, -one, zero, self.max.y),
+            Vector4::new(zero, zero, one, -self.min.z),
+            Vector4::new(zero, zero, -one, self.max.z),
+        ];
+        let mut planes = world;
+        for (local, v) in planes.iter_mut().zip(world.iter()) {
+            *local = Vector4::new(v.dot(&model.x), v.dot(&model.y),
+                                  v.dot(&model.z), v.dot(&model.w));
+        }
+        relate_planes(bound, &planes)
+    }
+}
+
+fn transform_point<S: cgmath::BaseFloat>(mx: &Matrix4<S>, p: &Point3<S>) -> Point3<S> {
+    use cgmath::{Matrix, Point};
+    Point3::from_homogeneous(&mx.mul_v(&p.to_homogeneous()))
+}
+
+fn get_max_scale<S: cgmath::BaseFloat>(mx: &Matrix4<S>) -> S {
+    bound::get_max_scale(&mx.x.truncate(), &mx.y.truncate(), &mx.z.truncate())
+}
+
+/// Relate to a sphere, given the squared distances from its center
+/// to the closest and the farthest points of a bound.
+fn relate_distance<S: cgmath::BaseFloat>(closest_sq: S, farthest_sq: S, radius: S) -> Relation {
+    if closest_sq > radius * radius {
+        Relation::Out
+    } else if farthest_sq <= radius * radius {
+        Relation::In
+    } else {
+        Relation::Cross
+    }
+}
+
+impl<S: cgmath::BaseFloat> Region<S, Sphere<S>> for Sphere<S> {
+    fn relate(&self, bound: &Sphere<S>, model: &Matrix4<S>) -> Relation {
+        use cgmath::{EuclideanVector, Point};
+        let center = transform_point(model, &bound.center);
+        let radius = bound.radius * get_max_scale(model);
+        let dist = center.sub_p(&self.center).length();
+        let closest = (dist - radius).max(S::zero());
+        let farthest = dist + radius;
+        relate_distance(closest * closest, farthest * farthest, self.radius)
+    }
+}
+
+impl<S: cgmath::BaseFloat> Region<S, Aabb3<S>> for Sphere<S> {
+    fn relate(&self, bound: &Aabb3<S>, model: &Matrix4<S>) -> Relation {
+        // world space box enclosing the transformed one
+        let mut min = Point3::new(S::infinity(), S::infinity(), S::infinity());
+        let mut max = Point3::new(S::neg_infinity(), S::neg_infinity(), S::neg_infinity());
+        let mut far_sq = S::zero();
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 {bound.min.x} else {bound.max.x},
+                if i & 2 == 0 {bound.min.y} else {bound.max.y},
+                if i & 4 == 0 {bound.min.z} else {bound.max.z});
+            let p = transform_point(model, &corner);
+            min = Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+            max = Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+            let d = Vector3::new(p.x - self.center.x, p.y - self.center.y, p.z - self.center.z);
+            far_sq = far_sq.max(d.x * d.x + d.y * d.y + d.z * d.z);
+        }
+        // closest point of the box to the center
+        let c = &self.center;
+        let dx = c.x.max(min.x).min(max.x) - c.x;
+        let dy = c.y.max(min.y).min(max.y) - c.y;
+        let dz = c.z.max(min.z).min(max.z) - c.z;
+        relate_distance(dx * dx + dy * dy + dz * dz, far_sq, self.radius)
+    }
+}
+
+/// Get all the visible entities whose bounds intersect a region.
+pub fn query<'b, R, M, S, E, I, G>(region: &G, entities: I) -> Vec<&'b E> where
+    R: gfx::Resources + 'b,
+    M: 'b,
+    S: cgmath::BaseFloat,
+    E: ::Entity<R, M> + 'b,
+    E::Transform: cgmath::Transform3<S>,
+    I: Iterator<Item = &'b E>,
+    G: Region<S, E::Bound>,
+{
+    entities.filter(|ent| {
+        let model: Matrix4<S> = ent.get_transform().into();
+        ent.is_visible() && region.relate(&ent.get_bound(), &model) != Relation::Out
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Matrix4, Point3};
+    use collision::{Aabb3, Relation, Sphere};
+    use super::Region;
+
+    fn identity() -> Matrix4<f32> {
+        Matrix4::new(1.0, 0.0, 0.0, 0.0,
+                     0.0, 1.0, 0.0, 0.0,
+                     0.0, 0.0, 1.0, 0.0,
+                     0.0, 0.0, 0.0, 1.0)
+    }
+
+    fn sphere(x: f32, y: f32, z: f32, radius: f32) -> Sphere<f32> {
+        Sphere { center: Point3::new(x, y, z), radius: radius }
+    }
+
+    #[test]
+    fn flat_box() {
+        let region = Aabb3::new(Point3::new(-1.0, -1.0, 0.0), Point3::new(1.0, 1.0, 0.0));
+        let model = identity();
+        assert_eq!(region.relate(&sphere(0.0, 0.0, 0.0, 0.5), &model), Relation::Cross);
+        assert_eq!(region.relate(&sphere(0.0, 0.0, 2.0, 0.5), &model), Relation::Out);
+        assert_eq!(region.relate(&sphere(3.0, 0.0, 0.0, 0.5), &model), Relation::Out);
+    }
+
+    #[test]
+    fn point_box() {
+        let p = Point3::new(1.0, 2.0, 3.0);
+        let region = Aabb3::new(p, p);
+        let model = identity();
+        assert_eq!(region.relate(&sphere(1.0, 2.0, 3.5, 1.0), &model), Relation::Cross);
+        assert_eq!(region.relate(&sphere(0.0, 0.0, 0.0, 1.0), &model), Relation::Out);
+    }
+
+    #[test]
+    fn solid_box() {
+        let region = Aabb3::new(Point3::new(-2.0, -2.0, -2.0), Point3::new(2.0, 2.0, 2.0));
+        let model = identity();
+        assert_eq!(region.relate(&sphere(0.0, 0.0, 0.0, 1.0), &model), Relation::In);
+        assert_eq!(region.relate(&sphere(2.0, 0.0, 0.0, 1.0), &model), Relation::Cross);
+    }
+}
@@ -0,0 +1,227 @@
+//! Camera-relative rendering for large worlds.
+//!
+//! Entities and the camera keep their transforms in `f64`. The model
+//! transforms are taken relative to the camera position before being
+//! converted to `f32`, so the precision is kept where it matters: close
+//! to the viewer.
+
+use std::marker::PhantomData;
+use cgmath;
+use cgmath::{Decomposed, Quaternion, Vector3};
+use collision;
+use gfx;
+use hprof;
+use cull::{Culler, Filter, Fix, Hook, enqueue_entities};
+use camera::{ClipDepth, DepthRange, Static, Transform};
+
+/// Convert a double precision transform into a single precision one.
+pub fn narrow(t: &Transform<f64>) -> Transform<f32> {
+    Decomposed {
+        scale: t.scale as f32,
+        rot: Quaternion::new(t.rot.s as f32, t.rot.v.x as f32,
+                             t.rot.v.y as f32, t.rot.v.z as f32),
+        disp: Vector3::new(t.disp.x as f32, t.disp.y as f32, t.disp.z as f32),
+    }
+}
+
+/// A camera with a double precision transform and a single precision
+/// projection, as required by `RelativeContext`. The wrapped camera is
+/// placed directly or with `Static::look_at`.
+#[derive(Clone, Debug)]
+pub struct RelativeCamera<P>(pub Static<f64, P>);
+
+impl<P> RelativeCamera<P> {
+    /// Create a new camera with an identity transform.
+    pub fn new(projection: P) -> RelativeCamera<P> {
+        RelativeCamera(Static::new(projection))
+    }
+}
+
+impl<P> ::Node for RelativeCamera<P> {
+    type Transform = Transform<f64>;
+    fn get_transform(&self) -> Transform<f64> {
+        self.0.transform.clone()
+    }
+}
+
+impl<P> ::Camera<f32> for RelativeCamera<P> where
+    P: Clone + Into<cgmath::Matrix4<f32>> + ClipDepth,
+{
+    type Projection = P;
+    fn get_projection(&self) -> P {
+        self.0.projection.clone()
+    }
+    fn get_depth_range(&self) -> DepthRange {
+        self.0.projection.get_depth_range()
+    }
+}
+
+/// Culler context with camera-relative transforms. The view information
+/// is given the model transform relative to the camera position, while
+/// the view transform and the projection are computed in `f32`.
+pub struct RelativeContext<'u, B, U> where
+    B: collision::Bound<f32>,
+    U: Culler<f32, B> + 'u,
+{
+    culler: &'u mut U,
+    origin: Vector3<f64>,
+    cam_inverse: Transform<f32>,
+    projection: cgmath::Matrix4<f32>,
+    view_projection: cgmath::Matrix4<f32>,
+    filter: Filter,
+    dummy: PhantomData<B>,
+}
+
+impl<'u,
+    B: collision::Bound<f32>,
+    U: Culler<f32, B>,
+> RelativeContext<'u, B, U> {
+    /// Create a new context, e.g. with a `RelativeCamera`.
+    pub fn new<C>(culler: &'u mut U, camera: &C) -> RelativeContext<'u, B, U> where
+        C: ::Camera<f32, Transform = Transform<f64>>,
+    {
+        use cgmath::{Matrix, Transform};
+        let cam = camera.get_transform();
+        // rotation and scale only, the position is the origin
+        let local = narrow(&Decomposed {
+            scale: cam.scale,
+            rot: cam.rot,
+            disp: Vector3::new(0.0, 0.0, 0.0),
+        });
+        let cam_inverse = local.invert().unwrap();
+        let mx_proj: cgmath::Matrix4<f32> = camera.get_projection().into();
+        let mx_view_proj = mx_proj.mul_m(&cam_inverse.clone().into());
+        culler.set_depth_range(camera.get_depth_range());
+        culler.init();
+        RelativeContext {
+            culler: culler,
+            origin: cam.disp,
+            cam_inverse: cam_inverse,
+            projection: mx_proj,
+            view_projection: mx_view_proj,
+            filter: Filter::new(),
+            dummy: PhantomData,
+        }
+    }
+
+    /// Set the layers of the pass. Entities outside of them are skipped.
+    pub fn set_layers(&mut self, layers: ::Layers) {
+        self.filter.layers = layers;
+    }
+
+    /// Get the layers of the pass.
+    pub fn get_layers(&self) -> ::Layers {
+        self.filter.layers
+    }
+
+    /// Only draw the shadow casters, e.g. for a shadow map pass.
+    pub fn set_casters_only(&mut self, casters_only: bool) {
+        self.filter.casters_only = casters_only;
+    }
+
+    /// Get the model transform of a node, relative to the camera position.
+    pub fn get_relative<N>(&self, node: &N) -> Transform<f32> where
+        N: ::Node<Transform = Transform<f64>>,
+    {
+        use cgmath::Vector;
+        let model = node.get_transform();
+        narrow(&Decomposed {
+            scale: model.scale,
+            rot: model.rot,
+            disp: model.disp.sub_v(&self.origin),
+        })
+    }
+
+    /// Check entity visibility.
+    pub fn is_visible<N, V>(&mut self, node: &N, bound: &B)
+                      -> Option<V> where
+        N: ::Node<Transform = Transform<f64>>,
+        V: ::ViewInfo<f32, Transform<f32>>,
+    {
+        use cgmath::{Matrix, Transform};
+        let model = self.get_relative(node);
+        let view = self.cam_inverse.concat(&model);
+        let mvp = self.view_projection.mul_m(&model.clone().into());
+        if self.culler.cull(bound, &mvp) != collision::Relation::Out {
+            Some(::ViewInfo::new(mvp, view, model))
+        }else {
+            None
+        }
+    }
+
+    /// Cull and draw the entities into a stream, letting a hook decide
+    /// what to draw for each visible entity.
+    pub fn draw_hooked<'b, R, M, E, I, V, H, X, K>(&mut self,
+                       entities: I, hook: &mut K, phase: &mut H, stream: &mut X)
+                       -> Result<::Report, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: ::Entity<R, M, Bound = B, Transform = Transform<f64>> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<f32, Transform<f32>>,
+        H: ::AbstractPhase<R, M, V>,
+        X: gfx::Stream<R>,
+        K: Hook<'b, R, M, E, f32, Transform<f32>, V>,
+    {
+        use cgmath::{Matrix, Transform, Vector};
+        let mut report = ::Report::new();
+
+        let g = hprof::enter("enqueue");
+        {
+            let RelativeContext { ref mut culler, origin, ref cam_inverse, ref projection,
+                                  ref view_projection, filter, .. } = *self;
+            try!(enqueue_entities(entities, &mut **culler, filter, projection,
+                |ent: &E| {
+                    let world = ent.get_transform();
+                    let model = narrow(&Decomposed {
+                        scale: world.scale,
+                        rot: world.rot,
+                        disp: world.disp.sub_v(&origin),
+                    });
+                    let mvp = view_projection.mul_m(&model.clone().into());
+                    (model, mvp)
+                },
+                |model: &::camera::Transform<f32>| cam_inverse.concat(model),
+                hook, phase, &mut report));
+        }
+        drop(g);
+
+        let _g = hprof::enter("flush");
+        match phase.flush(stream) {
+            Ok(()) => Ok(report),
+            Err(e) => Err(::Error::Flush(e)),
+        }
+    }
+
+    /// Cull and draw the entities into a stream, adjusting the view
+    /// information of each visible entity with a closure.
+    pub fn draw_with<'b, R, M, E, I, V, H, X, F>(&mut self,
+                     entities: I, fix: F, phase: &mut H, stream: &mut X)
+                     -> Result<::Report, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: ::Entity<R, M, Bound = B, Transform = Transform<f64>> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<f32, Transform<f32>>,
+        H: ::AbstractPhase<R, M, V>,
+        X: gfx::Stream<R>,
+        F: FnMut(&E, &mut V),
+    {
+        self.draw_hooked(entities, &mut Fix(fix), phase, stream)
+    }
+
+    /// Cull and draw the entities into a stream.
+    pub fn draw<'b, R, M, E, I, V, H, X>(&mut self,
+                entities: I, phase: &mut H, stream: &mut X)
+                -> Result<::Report, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: ::Entity<R, M, Bound = B, Transform = Transform<f64>> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<f32, Transform<f32>>,
+        H: ::AbstractPhase<R, M, V>,
+        X: gfx::Stream<R>,
+    {
+        self.draw_with(entities, |_: &E, _: &mut V| {}, phase, stream)
+    }
+}
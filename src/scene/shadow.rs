@@ -0,0 +1,283 @@
+//! Shadow map helpers: cascades, caster culling, and receiver flags.
+
+use std::marker::PhantomData;
+use cgmath;
+use cgmath::{Decomposed, Matrix3, Ortho, PerspectiveFov, Point3, Quaternion, Rad, Vector3};
+use collision;
+use gfx;
+use camera::{DepthRange, Transform};
+use cull::{Culler, Context, get_clip_planes, relate_planes};
+
+/// Compute the split distances of `count` cascades, using the practical
+/// split scheme: a blend between the logarithmic (`lambda = 1`) and the
+/// uniform (`lambda = 0`) splits. Returns `count + 1` distances, starting
+/// with `near` and ending with `far`.
+pub fn split_distances<S: cgmath::BaseFloat>(near: S, far: S, count: usize, lambda: S)
+                       -> Vec<S> {
+    let num = S::from(count).unwrap();
+    (0..count+1).map(|i| {
+        let k = S::from(i).unwrap() / num;
+        let log = near * (far / near).powf(k);
+        let uniform = near + (far - near) * k;
+        lambda * log + (S::one() - lambda) * uniform
+    }).collect()
+}
+
+/// Compute the rotation of a light camera, looking in a given direction.
+pub fn light_rotation<S: cgmath::BaseFloat>(direction: &Vector3<S>) -> Quaternion<S> {
+    let (right, up, back) = light_basis(direction);
+    Quaternion::from(Matrix3::from_cols(right, up, back))
+}
+
+fn light_basis<S: cgmath::BaseFloat>(direction: &Vector3<S>)
+               -> (Vector3<S>, Vector3<S>, Vector3<S>) {
+    use cgmath::{EuclideanVector, Vector};
+    let forward = direction.normalize();
+    let (zero, one) = (S::zero(), S::one());
+    let guide = if forward.y.abs() > S::from(0.99).unwrap() {
+        Vector3::new(one, zero, zero)
+    } else {
+        Vector3::new(zero, one, zero)
+    };
+    let right = forward.cross(&guide).normalize();
+    let up = right.cross(&forward);
+    (right, up, forward.neg_v())
+}
+
+/// A camera of a single shadow cascade, usable with `Context`.
+#[derive(Clone, Debug)]
+pub struct Cascade<S> {
+    /// Light space orientation and position.
+    pub transform: Transform<S>,
+    /// Orthographic projection, enclosing the split.
+    pub projection: Ortho<S>,
+    /// Distance to the split start from the viewer.
+    pub near: S,
+    /// Distance to the split end from the viewer.
+    pub far: S,
+}
+
+impl<S: cgmath::BaseFloat> ::Node for Cascade<S> {
+    type Transform = Transform<S>;
+    fn get_transform(&self) -> Transform<S> {
+        self.transform.clone()
+    }
+}
+
+impl<S: cgmath::BaseFloat> ::Camera<S> for Cascade<S> {
+    type Projection = Ortho<S>;
+    fn get_projection(&self) -> Ortho<S> {
+        self.projection.clone()
+    }
+}
+
+/// Cascaded shadow map configuration.
+#[derive(Clone, Debug)]
+pub struct Cascades<S> {
+    /// Number of cascades.
+    pub count: usize,
+    /// Split scheme blending factor, see `split_distances`.
+    pub lambda: S,
+    /// Shadow map resolution, used for texel snapping.
+    pub resolution: u32,
+}
+
+impl<S: cgmath::BaseFloat> Cascades<S> {
+    /// Split the frustum of a perspective camera into cascades, computing
+    /// an orthographic projection around each split, as seen from a light
+    /// shining in a given direction. Each projection covers the bounding
+    /// sphere of its split, so the size stays the same while the camera
+    /// rotates, and its center is snapped to the shadow map texels, so the
+    /// texels don't shimmer while the camera moves. The camera transform
+    /// is expected to have no scale.
+    pub fn build<T>(&self, transform: &T, projection: &PerspectiveFov<S, Rad<S>>,
+                    light_dir: &Vector3<S>) -> Vec<Cascade<S>> where
+        T: cgmath::Transform3<S>,
+    {
+        use cgmath::{Point, Vector};
+        let distances = split_distances(projection.near, projection.far,
+                                        self.count, self.lambda);
+        let (one, two) = (S::one(), S::from(2).unwrap());
+        let tan = (projection.fovy.s / two).tan();
+        // squared ratio of the corner distance from the axis to the depth
+        let k2 = tan * tan * (one + projection.aspect * projection.aspect);
+        let (right, up, back) = light_basis(light_dir);
+        let rotation = light_rotation(light_dir);
+        let resolution = S::from(self.resolution).unwrap();
+        distances.windows(2).map(|pair| {
+            let (near, far) = (pair[0], pair[1]);
+            // bounding sphere of the split, centered on the view axis,
+            // equally distant from the near and the far corners unless
+            // that would put it beyond the far plane
+            let center = ((near + far) * (one + k2) / two).min(far);
+            let near_sq = (center - near) * (center - near) + near * near * k2;
+            let far_sq = (far - center) * (far - center) + far * far * k2;
+            let radius = near_sq.max(far_sq).sqrt();
+            let zero = S::zero();
+            let p = transform.transform_point(&Point3::new(zero, zero, -center)).to_vec();
+            let l = Vector3::new(p.dot(&right), p.dot(&up), p.dot(&back));
+            // texel snapping, keeping the width at exactly `resolution` texels
+            let texel = radius * two / resolution;
+            let (x, y) = ((l.x / texel).floor() * texel,
+                          (l.y / texel).floor() * texel);
+            Cascade {
+                transform: Decomposed {
+                    scale: S::one(),
+                    rot: rotation,
+                    disp: back.mul_s(l.z + radius),
+                },
+                projection: Ortho {
+                    left: x - radius,
+                    right: x + radius,
+                    bottom: y - radius,
+                    top: y + radius,
+                    near: S::zero(),
+                    far: radius * two,
+                },
+                near: near,
+                far: far,
+            }
+        }).collect()
+    }
+}
+
+/// Culler of shadow casters. It ignores the near plane of the light
+/// frustum, extending it towards the light, so that the casters between
+/// the light and the visible volume are kept even if they are off-screen.
+/// Their depth is expected to be clamped when rendering the shadow map.
+#[derive(Clone, Debug)]
+pub struct CasterFrustum<S, B> {
+    depth: DepthRange,
+    dummy: PhantomData<(S, B)>,
+}
+
+impl<S, B> CasterFrustum<S, B> {
+    /// Create a new caster culler, assuming the standard GL depth range.
+    pub fn new() -> CasterFrustum<S, B> {
+        CasterFrustum::with_depth(DepthRange::NegativeOneToOne)
+    }
+
+    /// Create a new caster culler for a given clip space depth convention.
+    pub fn with_depth(depth: DepthRange) -> CasterFrustum<S, B> {
+        CasterFrustum {
+            depth: depth,
+            dummy: PhantomData,
+        }
+    }
+}
+
+impl<S: cgmath::BaseFloat, B: collision::Bound<S>> Culler<S, B> for CasterFrustum<S, B> {
+    fn init(&mut self) {}
+    fn set_depth_range(&mut self, depth: DepthRange) {
+        self.depth = depth;
+    }
+    fn cull(&mut self, bound: &B, mvp: &cgmath::Matrix4<S>) -> collision::Relation {
+        let p = get_clip_planes(mvp, self.depth);
+        // all but the near plane, which comes 5th
+        relate_planes(bound, &[p[0], p[1], p[2], p[3], p[5]])
+    }
+}
+
+/// View information able to carry the shadow receiving flag, to be
+/// consumed by `Technique::fix_params`, e.g. to skip the shadow lookup.
+pub trait ShadowInfo {
+    /// Set whether the entity receives shadows.
+    fn set_receiver(&mut self, bool);
+}
+
+impl<'u,
+    S: cgmath::BaseFloat,
+    B: collision::Bound<S>,
+    T: cgmath::Transform3<S> + Clone,
+    U: Culler<S, B>,
+> Context<'u, S, B, T, U> {
+    /// Cull and draw the entities into a stream, passing the shadow
+    /// receiving flag of each through the view information.
+    pub fn draw_shadowed<'b, R, M, E, I, V, H, X>(&mut self,
+                         entities: I, phase: &mut H, stream: &mut X)
+                         -> Result<::Report, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: ::Entity<R, M, Bound = B, Transform = T> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<S, T> + ShadowInfo,
+        H: ::AbstractPhase<R, M, V>,
+        X: gfx::Stream<R>,
+    {
+        self.draw_with(entities, |ent: &E, view_info: &mut V| {
+            view_info.set_receiver(ent.is_shadow_receiver());
+        }, phase, stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Decomposed, PerspectiveFov, Quaternion, Rad, Rotation3, Vector3};
+    use super::{Cascades, split_distances};
+
+    fn approx(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn split_uniform() {
+        let d = split_distances(1.0f64, 101.0, 4, 0.0);
+        assert_eq!(d.len(), 5);
+        for (&a, &b) in d.iter().zip([1.0, 26.0, 51.0, 76.0, 101.0].iter()) {
+            assert!(approx(a, b), "{:?}", d);
+        }
+    }
+
+    #[test]
+    fn split_logarithmic() {
+        let d = split_distances(1.0f64, 10000.0, 4, 1.0);
+        for (&a, &b) in d.iter().zip([1.0, 10.0, 100.0, 1000.0, 10000.0].iter()) {
+            assert!(approx(a, b), "{:?}", d);
+        }
+    }
+
+    #[test]
+    fn split_endpoints() {
+        for &lambda in [0.0, 0.3, 0.5, 0.9, 1.0].iter() {
+            let d = split_distances(0.5f64, 300.0, 3, lambda);
+            assert_eq!(d.len(), 4);
+            assert!(approx(d[0], 0.5) && approx(d[3], 300.0), "{:?}", d);
+            assert!(d.windows(2).all(|w| w[0] < w[1]), "{:?}", d);
+        }
+    }
+
+    #[test]
+    fn stable_under_rotation() {
+        let cascades = Cascades { count: 3, lambda: 0.7f64, resolution: 1024 };
+        let projection = PerspectiveFov {
+            fovy: Rad { s: 1.0 },
+            aspect: 1.5,
+            near: 0.5,
+            far: 200.0,
+        };
+        let light = Vector3::new(0.3, -1.0, 0.2);
+        let build = |angle: f64| {
+            let rot: Quaternion<f64> = Rotation3::from_angle_y(Rad { s: angle });
+            let transform = Decomposed {
+                scale: 1.0,
+                rot: rot,
+                disp: Vector3::new(10.0, 2.0, -7.0),
+            };
+            cascades.build(&transform, &projection, &light)
+        };
+        let reference = build(0.0);
+        for i in 1..16 {
+            let cascades = build(i as f64 * 0.4);
+            for (c, r) in cascades.iter().zip(reference.iter()) {
+                let width = c.projection.right - c.projection.left;
+                // the size doesn't depend on the view direction
+                assert!(approx(width, r.projection.right - r.projection.left));
+                assert!(approx(c.projection.far, r.projection.far));
+                // the center is snapped to the texels
+                let texel = width / 1024.0;
+                let offset = (c.projection.left + c.projection.right) / 2.0 / texel;
+                assert!((offset - offset.round()).abs() < 1e-6, "{}", offset);
+            }
+        }
+    }
+}
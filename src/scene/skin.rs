@@ -0,0 +1,221 @@
+//! Skeletal skinning.
+
+use cgmath;
+use cgmath::{Matrix4, Point3};
+use collision;
+use collision::Aabb3;
+use gfx;
+use bound::Transformable;
+use cull::{Culler, Context};
+use camera::Transform;
+
+/// Joint skinning matrix, column-major, as stored in the palette buffer.
+pub type JointMatrix = [[f32; 4]; 4];
+
+/// View information able to carry the location of the entity joint
+/// matrices in the palette buffer, to be consumed by `Technique::fix_params`.
+pub trait SkinInfo {
+    /// Set the offset and the number of joint matrices.
+    fn set_palette(&mut self, offset: u32, count: u32);
+}
+
+/// A joint of a skeleton.
+#[derive(Clone, Debug)]
+pub struct Joint<S> {
+    /// Joint name.
+    pub name: String,
+    /// Index of the parent joint, which has to precede this one.
+    pub parent: Option<usize>,
+    /// Model -> joint transform in the bind pose.
+    pub inverse_bind: Matrix4<S>,
+    /// Local transform in the rest pose.
+    pub rest: Transform<S>,
+    /// Bound of the vertices influenced by the joint, in the bind pose.
+    pub bound: Option<Aabb3<S>>,
+}
+
+/// Joint hierarchy.
+#[derive(Clone, Debug)]
+pub struct Skeleton<S> {
+    /// Joints, parents preceding children.
+    pub joints: Vec<Joint<S>>,
+}
+
+impl<S: cgmath::BaseFloat> Skeleton<S> {
+    /// Compute the bounds of the joints from the vertex positions, joint
+    /// indices and weights. Needed for `Pose::get_bound`.
+    pub fn compute_bounds(&mut self, positions: &[Point3<S>],
+                          joints: &[[u16; 4]], weights: &[[S; 4]]) {
+        for joint in self.joints.iter_mut() {
+            joint.bound = None;
+        }
+        for ((pos, ids), ws) in positions.iter().zip(joints.iter()).zip(weights.iter()) {
+            for (&id, &w) in ids.iter().zip(ws.iter()) {
+                if w <= S::zero() {
+                    continue
+                }
+                if let Some(joint) = self.joints.get_mut(id as usize) {
+                    joint.bound = Some(match joint.bound {
+                        Some(ref b) => Aabb3::new(
+                            Point3::new(b.min.x.min(pos.x), b.min.y.min(pos.y), b.min.z.min(pos.z)),
+                            Point3::new(b.max.x.max(pos.x), b.max.y.max(pos.y), b.max.z.max(pos.z))),
+                        None => Aabb3::new(*pos, *pos),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Create a pose with every joint at rest.
+    pub fn rest_pose(&self) -> Pose<S> {
+        let mut pose = Pose {
+            locals: self.joints.iter().map(|j| j.rest.clone()).collect(),
+            worlds: Vec::new(),
+            palette: Vec::new(),
+            bound: None,
+        };
+        pose.update(self);
+        pose
+    }
+}
+
+/// Pose of a skeleton. The local joint transforms are expected to be
+/// written, e.g. by `anim::Clip::apply`, followed by `update`.
+#[derive(Clone, Debug)]
+pub struct Pose<S> {
+    /// Joint -> parent transforms.
+    pub locals: Vec<Transform<S>>,
+    worlds: Vec<Matrix4<S>>,
+    palette: Vec<Matrix4<S>>,
+    bound: Option<Aabb3<S>>,
+}
+
+impl<S: cgmath::BaseFloat> Pose<S> {
+    /// Evaluate the joint hierarchy, computing the skinning matrices and
+    /// the bound of the posed mesh.
+    pub fn update(&mut self, skeleton: &Skeleton<S>) {
+        use cgmath::Matrix;
+        self.worlds.clear();
+        self.palette.clear();
+        self.bound = None;
+        for (joint, local) in skeleton.joints.iter().zip(self.locals.iter()) {
+            let local: Matrix4<S> = local.clone().into();
+            let world = match joint.parent {
+                Some(p) if p < self.worlds.len() => self.worlds[p].mul_m(&local),
+                _ => local,
+            };
+            let skin = world.mul_m(&joint.inverse_bind);
+            if let Some(ref b) = joint.bound {
+                let b = b.transform(&skin);
+                self.bound = Some(match self.bound {
+                    Some(ref a) => Aabb3::new(
+                        Point3::new(a.min.x.min(b.min.x), a.min.y.min(b.min.y), a.min.z.min(b.min.z)),
+                        Point3::new(a.max.x.max(b.max.x), a.max.y.max(b.max.y), a.max.z.max(b.max.z))),
+                    None => b,
+                });
+            }
+            self.worlds.push(world);
+            self.palette.push(skin);
+        }
+    }
+
+    /// Get the joint -> model transforms.
+    pub fn get_joint_transforms(&self) -> &[Matrix4<S>] {
+        &self.worlds
+    }
+
+    /// Get the skinning matrices, bind pose model -> posed model.
+    pub fn get_palette(&self) -> &[Matrix4<S>] {
+        &self.palette
+    }
+
+    /// Get the model space bound of the posed mesh, to be returned by
+    /// `Entity::get_bound`. Requires the joint bounds to be computed.
+    pub fn get_bound(&self) -> Option<Aabb3<S>> {
+        self.bound.clone()
+    }
+}
+
+/// Shared buffer of joint matrices of all the skinned entities, filled
+/// every frame and bound by the technique.
+pub struct Palette<R: gfx::Resources> {
+    buffer: gfx::handle::Buffer<R, JointMatrix>,
+    capacity: usize,
+    data: Vec<JointMatrix>,
+}
+
+impl<R: gfx::Resources> Palette<R> {
+    /// Create a new palette, allocating the buffer for a number of matrices.
+    pub fn new<F: gfx::Factory<R>>(factory: &mut F, capacity: usize) -> Palette<R> {
+        Palette {
+            buffer: factory.create_buffer_dynamic(capacity, gfx::BufferRole::Uniform),
+            capacity: capacity,
+            data: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Get the buffer handle.
+    pub fn get_buffer(&self) -> &gfx::handle::Buffer<R, JointMatrix> {
+        &self.buffer
+    }
+
+    /// Remove all the matrices.
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    /// Add the matrices of a pose, returning their offset and count,
+    /// or `None` if the buffer is full.
+    pub fn push(&mut self, pose: &Pose<f32>) -> Option<(u32, u32)> {
+        let count = pose.palette.len();
+        if self.data.len() + count > self.capacity {
+            return None
+        }
+        let offset = self.data.len();
+        self.data.extend(pose.palette.iter().map(|m| (*m).into()));
+        Some((offset as u32, count as u32))
+    }
+
+    /// Upload the matrices into the buffer, ordered in the stream before
+    /// the draw calls using them.
+    pub fn upload<X: gfx::Stream<R>>(&self, stream: &mut X) {
+        if !self.data.is_empty() {
+            stream.access().0.update_buffer_vec(&self.buffer, &self.data, 0);
+        }
+    }
+}
+
+/// An entity deformed by a skeleton. Its bound is expected to cover
+/// the current pose.
+pub trait SkinnedEntity<R: gfx::Resources, M>: ::Entity<R, M> {
+    /// Get the offset and the number of the entity joint matrices in the
+    /// palette, as returned by `Palette::push`.
+    fn get_palette_range(&self) -> (u32, u32);
+}
+
+impl<'u,
+    S: cgmath::BaseFloat,
+    B: collision::Bound<S>,
+    T: cgmath::Transform3<S> + Clone,
+    U: Culler<S, B>,
+> Context<'u, S, B, T, U> {
+    /// Cull and draw the skinned entities into a stream. The view
+    /// information receives the palette range of each entity. The palette
+    /// is expected to be uploaded into the same stream beforehand.
+    pub fn draw_skinned<'b, R, M, E, I, V, H, X>(&mut self,
+                        entities: I, phase: &mut H, stream: &mut X)
+                        -> Result<::Report, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        E: SkinnedEntity<R, M, Bound = B, Transform = T> + 'b,
+        I: Iterator<Item = &'b E>,
+        V: ::ViewInfo<S, T> + SkinInfo,
+        H: ::AbstractPhase<R, M, V>,
+        X: gfx::Stream<R>,
+    {
+        self.draw_with(entities, |ent: &E, view_info: &mut V| {
+            let (offset, count) = ent.get_palette_range();
+            view_info.set_palette(offset, count);
+        }, phase, stream)
+    }
+}
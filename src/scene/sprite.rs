@@ -0,0 +1,157 @@
+//! Camera-facing sprites.
+
+use cgmath;
+use cgmath::{Decomposed, Matrix3, Point3, Quaternion, Vector3};
+use collision::Sphere;
+use gfx;
+use cull::{Culler, Context};
+use camera::Transform;
+
+/// Orientation mode of a sprite.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Facing<S> {
+    /// Parallel to the view plane, like particles.
+    Spherical,
+    /// Rotating around a world axis only, like trees or lamp posts.
+    Cylindrical(Vector3<S>),
+}
+
+/// A sprite. The mesh is expected to be a unit quad, centered at the
+/// origin in the XY plane, facing +Z.
+pub struct Sprite<R: gfx::Resources, M, S> {
+    /// Quad mesh.
+    pub mesh: gfx::Mesh<R>,
+    /// Drawable fragments.
+    pub fragments: Vec<::Fragment<R, M>>,
+    /// World space center.
+    pub position: Point3<S>,
+    /// Size of the quad side.
+    pub size: S,
+    /// Orientation mode.
+    pub facing: Facing<S>,
+    /// Visibility flag.
+    pub visible: bool,
+    /// Render layers.
+    pub layers: ::Layers,
+}
+
+/// Get the local bound of the unit quad, covering it in any orientation.
+pub fn get_quad_bound<S: cgmath::BaseFloat>() -> Sphere<S> {
+    let zero = S::zero();
+    let half = S::one() / (S::one() + S::one());
+    Sphere {
+        center: Point3::new(zero, zero, zero),
+        // half of the quad diagonal
+        radius: half.sqrt(),
+    }
+}
+
+/// Compute the transform of a quad of a given size and position, facing
+/// a camera, given its camera -> world transform. Useful for orienting
+/// the instances of an `instance::Group` of sprites, drawn with a single
+/// call per fragment.
+pub fn face<S: cgmath::BaseFloat>(position: &Point3<S>, size: S, facing: Facing<S>,
+                                  camera: &Transform<S>) -> Transform<S> {
+    use cgmath::{EuclideanVector, Point, Rotation, Vector};
+    let rot = match facing {
+        Facing::Spherical => camera.rot,
+        Facing::Cylindrical(axis) => {
+            let up = axis.normalize();
+            let to_camera = Point3::from_vec(&camera.disp).sub_p(position);
+            let forward = to_camera.sub_v(&up.mul_s(to_camera.dot(&up)));
+            let back = if forward.length2() > S::epsilon() {
+                forward.normalize()
+            } else {
+                // looking along the axis: face the view direction instead
+                let zero = S::zero();
+                let view_back = camera.rot.rotate_vector(&Vector3::new(zero, zero, S::one()));
+                let v = view_back.sub_v(&up.mul_s(view_back.dot(&up)));
+                if v.length2() > S::epsilon() {
+                    v.normalize()
+                } else {
+                    camera.rot.rotate_vector(&Vector3::new(zero, -S::one(), zero))
+                }
+            };
+            let right = up.cross(&back);
+            Quaternion::from(Matrix3::from_cols(right, up, back))
+        },
+    };
+    Decomposed {
+        scale: size,
+        rot: rot,
+        disp: position.to_vec(),
+    }
+}
+
+impl<R: gfx::Resources, M, S: cgmath::BaseFloat> Sprite<R, M, S> {
+    /// Get the local bound, covering the quad in any orientation.
+    pub fn get_bound(&self) -> Sphere<S> {
+        get_quad_bound()
+    }
+
+    /// Compute the sprite transform facing a camera, given its
+    /// camera -> world transform.
+    pub fn get_transform(&self, camera: &Transform<S>) -> Transform<S> {
+        face(&self.position, self.size, self.facing, camera)
+    }
+}
+
+/// A sprite oriented towards the camera, drawn as a regular entity.
+struct Oriented<'a, R: gfx::Resources + 'a, M: 'a, S: 'a> {
+    sprite: &'a Sprite<R, M, S>,
+    transform: Transform<S>,
+}
+
+impl<'a, R: gfx::Resources, M, S: cgmath::BaseFloat> ::Node for Oriented<'a, R, M, S> {
+    type Transform = Transform<S>;
+    fn get_transform(&self) -> Transform<S> {
+        self.transform.clone()
+    }
+}
+
+impl<'a, R: gfx::Resources, M, S: cgmath::BaseFloat> ::Entity<R, M> for Oriented<'a, R, M, S> {
+    type Bound = Sphere<S>;
+    fn is_visible(&self) -> bool {
+        self.sprite.visible
+    }
+    fn get_layers(&self) -> ::Layers {
+        self.sprite.layers
+    }
+    fn get_bound(&self) -> Sphere<S> {
+        self.sprite.get_bound()
+    }
+    fn get_mesh(&self) -> &gfx::Mesh<R> {
+        &self.sprite.mesh
+    }
+    fn get_fragments(&self) -> &[::Fragment<R, M>] {
+        &self.sprite.fragments
+    }
+}
+
+impl<'u,
+    S: cgmath::BaseFloat,
+    U: Culler<S, Sphere<S>>,
+> Context<'u, S, Sphere<S>, Transform<S>, U> {
+    /// Orient the sprites towards the camera, cull them, and draw them
+    /// into a stream, with a call per fragment of each sprite. Sprites
+    /// count as shadow casters. Large numbers of sprites are better
+    /// oriented with `face` and drawn as instanced groups.
+    pub fn draw_sprites<'b, R, M, I, V, H, X>(&mut self,
+                        sprites: I, phase: &mut H, stream: &mut X)
+                        -> Result<::Report, ::Error> where
+        R: gfx::Resources + 'b,
+        M: 'b,
+        S: 'b,
+        I: Iterator<Item = &'b Sprite<R, M, S>>,
+        V: ::ViewInfo<S, Transform<S>>,
+        H: ::AbstractPhase<R, M, V>,
+        X: gfx::Stream<R>,
+    {
+        let camera = cgmath::Transform::invert(self.get_camera_inverse()).unwrap();
+        let oriented: Vec<_> = sprites.map(|sprite| Oriented {
+            sprite: sprite,
+            transform: sprite.get_transform(&camera),
+        }).collect();
+        self.draw(oriented.iter(), phase, stream)
+    }
+}